@@ -0,0 +1,3062 @@
+/*! The CPU itself: its instructions, its registers and the state running a program */
+
+use std::collections::HashMap;
+use std::io::Write;
+
+pub use crate::device::Device;
+use crate::device::MappedDevice;
+pub use crate::input::InputSource;
+use crate::input::StdinInput;
+pub use crate::memory::{MemoryBacking, MemoryError, MemoryState, SubStack, WatchedSlot};
+pub use crate::names::Name;
+use crate::random::Rng;
+pub use crate::word::Word;
+
+#[derive(Debug)]
+/** Errors an emulated program can trigger at runtime.
+They stop the CPU instead of crashing the host */
+pub enum CpuError {
+    /** A DIV or MOD instruction was executed with a divisor of 0 */
+    DivisionByZero,
+    /** An arithmetic instruction overflowed while trapping on overflow */
+    Overflow { first: u64, second: u64 },
+    /** A POP instruction was executed on an empty sub stack */
+    StackUnderflow,
+    /** A GOTOLABEL instruction refers to a label that was never declared */
+    UnknownLabel(Name),
+    /** A register name doesn't match any register */
+    UnknownRegister(String),
+    /** An instruction tried to write to a read-only register */
+    ReadOnlyRegister(Register),
+    /** A float instruction got an argument that isn't a float register */
+    NotAFloatRegister,
+    /** An integer instruction got a float register, floats only go through float instructions and MOV */
+    UnexpectedFloatRegister,
+    /** A MOV instruction writes to "res" while the CPU protects it */
+    ProtectedResult,
+    /** An instruction tried to write to a value instead of a register or a memory slot */
+    InvalidDestination(i64),
+    /** A signed value is out of the range of the word read as a signed integer */
+    SignedValueOutOfRange(i16),
+    /** A jump targets an address outside of the instruction cache */
+    JumpOutOfBounds(i32),
+    /** A CALLINDIRECT instruction targets an address that doesn't hold a function declaration */
+    NotAFunction(u64),
+    /** A CALL-like instruction refers to a function that was never declared */
+    UnknownFunction(Name),
+    /** A call went beyond the maximum call depth */
+    StackOverflow { depth: usize, fn_name: Name },
+    /** The CPU couldn't write to its output */
+    Io(std::io::Error),
+    /** A READ instruction found no more input, or a value that doesn't fit in a word */
+    NoInput,
+    /** A SYSCALL instruction used a number without a registered handler */
+    UnknownSyscall(u64),
+    /** An interrupt was raised without a registered handler */
+    UnhandledInterrupt(u64),
+    /** A CUSTOM instruction used an opcode without a registered handler */
+    IllegalInstruction(u16),
+    /** An ASSERT instruction compared two different values */
+    AssertionFailed { actual: u64, expected: u64 },
+    /** Two FN instructions declare the same function */
+    DuplicateFunction {
+        name: Name,
+        first_address: u16,
+        second_address: u16,
+    },
+    /** A device was mapped over addresses already used by another device */
+    OverlappingDevice { start: u16, length: u16 },
+    /** A MEMCOPY or MEMSET instruction got a register or a value where it needs a memory argument */
+    NotAMemoryLocation,
+    /** A range of slots goes past the last address */
+    RangeOutOfBounds { address: u64, length: u64 },
+    /** An instruction read past the end of the data segment */
+    DataOutOfBounds { address: u64, size: u64 },
+    /** An instruction tried to write to the read-only data segment */
+    WriteToReadOnly(u64),
+    /** An instruction accessed memory it is not allowed to */
+    Memory(MemoryError),
+    /** The canary or the return address of a sub stack changed while its function was running */
+    FrameCorrupted { depth: usize, fn_name: Name },
+    /** The program was started again, a CPU runs it once and `resume` continues it after a BRK */
+    AlreadyStarted,
+    /** An error along with the function calls in progress when it stopped the CPU */
+    Backtraced {
+        error: Box<CpuError>,
+        backtrace: Backtrace,
+    },
+}
+impl CpuError {
+    /** The error itself, without the backtrace added when it stopped the CPU */
+    pub fn inner(&self) -> &CpuError {
+        match self {
+            CpuError::Backtraced { error, .. } => error.inner(),
+            _ => self,
+        }
+    }
+}
+impl Clone for CpuError {
+    fn clone(&self) -> Self {
+        match self {
+            // I/O errors can't be cloned, a copy keeps their kind and message
+            CpuError::Io(error) => {
+                CpuError::Io(std::io::Error::new(error.kind(), error.to_string()))
+            }
+            CpuError::DivisionByZero => CpuError::DivisionByZero,
+            CpuError::Overflow { first, second } => CpuError::Overflow {
+                first: *first,
+                second: *second,
+            },
+            CpuError::StackUnderflow => CpuError::StackUnderflow,
+            CpuError::UnknownLabel(label_name) => CpuError::UnknownLabel(label_name.clone()),
+            CpuError::UnknownRegister(register_name) => {
+                CpuError::UnknownRegister(register_name.clone())
+            }
+            CpuError::ReadOnlyRegister(register) => CpuError::ReadOnlyRegister(*register),
+            CpuError::NotAFloatRegister => CpuError::NotAFloatRegister,
+            CpuError::UnexpectedFloatRegister => CpuError::UnexpectedFloatRegister,
+            CpuError::ProtectedResult => CpuError::ProtectedResult,
+            CpuError::InvalidDestination(value) => CpuError::InvalidDestination(*value),
+            CpuError::SignedValueOutOfRange(value) => CpuError::SignedValueOutOfRange(*value),
+            CpuError::JumpOutOfBounds(address) => CpuError::JumpOutOfBounds(*address),
+            CpuError::NotAFunction(address) => CpuError::NotAFunction(*address),
+            CpuError::UnknownFunction(fn_name) => CpuError::UnknownFunction(fn_name.clone()),
+            CpuError::StackOverflow { depth, fn_name } => CpuError::StackOverflow {
+                depth: *depth,
+                fn_name: fn_name.clone(),
+            },
+            CpuError::NoInput => CpuError::NoInput,
+            CpuError::UnknownSyscall(number) => CpuError::UnknownSyscall(*number),
+            CpuError::UnhandledInterrupt(number) => CpuError::UnhandledInterrupt(*number),
+            CpuError::IllegalInstruction(code) => CpuError::IllegalInstruction(*code),
+            CpuError::AssertionFailed { actual, expected } => CpuError::AssertionFailed {
+                actual: *actual,
+                expected: *expected,
+            },
+            CpuError::DuplicateFunction {
+                name,
+                first_address,
+                second_address,
+            } => CpuError::DuplicateFunction {
+                name: name.clone(),
+                first_address: *first_address,
+                second_address: *second_address,
+            },
+            CpuError::OverlappingDevice { start, length } => CpuError::OverlappingDevice {
+                start: *start,
+                length: *length,
+            },
+            CpuError::NotAMemoryLocation => CpuError::NotAMemoryLocation,
+            CpuError::RangeOutOfBounds { address, length } => CpuError::RangeOutOfBounds {
+                address: *address,
+                length: *length,
+            },
+            CpuError::DataOutOfBounds { address, size } => CpuError::DataOutOfBounds {
+                address: *address,
+                size: *size,
+            },
+            CpuError::WriteToReadOnly(address) => CpuError::WriteToReadOnly(*address),
+            CpuError::Memory(error) => CpuError::Memory(error.clone()),
+            CpuError::FrameCorrupted { depth, fn_name } => CpuError::FrameCorrupted {
+                depth: *depth,
+                fn_name: fn_name.clone(),
+            },
+            CpuError::AlreadyStarted => CpuError::AlreadyStarted,
+            CpuError::Backtraced { error, backtrace } => CpuError::Backtraced {
+                error: error.clone(),
+                backtrace: backtrace.clone(),
+            },
+        }
+    }
+}
+impl From<std::io::Error> for CpuError {
+    fn from(error: std::io::Error) -> Self {
+        CpuError::Io(error)
+    }
+}
+impl From<MemoryError> for CpuError {
+    fn from(error: MemoryError) -> Self {
+        CpuError::Memory(error)
+    }
+}
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpuError::DivisionByZero => write!(f, "Division by zero"),
+            CpuError::Overflow { first, second } => {
+                write!(f, "Arithmetic overflow with operands {first} and {second}")
+            }
+            CpuError::StackUnderflow => write!(f, "Cannot pop a value from an empty sub stack"),
+            CpuError::UnknownLabel(label_name) => write!(f, "Label \"{label_name}\" not found"),
+            CpuError::UnknownRegister(register_name) => write!(
+                f,
+                "Register \"{register_name}\" not found, the registers are {}, and {} as aliases, the float registers are {}",
+                Register::NAMES.join(", "),
+                Register::ALIASES.map(|(alias, _)| alias).join(", "),
+                FloatRegister::NAMES.join(", ")
+            ),
+            CpuError::ReadOnlyRegister(register) => {
+                write!(f, "Cannot write to the read-only register \"{register}\"")
+            }
+            CpuError::NotAFloatRegister => {
+                write!(f, "Expected a float register, not an integer argument")
+            }
+            CpuError::UnexpectedFloatRegister => write!(
+                f,
+                "Expected an integer argument, not a float register, floats are converted with FLOATTOINT"
+            ),
+            CpuError::ProtectedResult => write!(
+                f,
+                "Cannot move a value to \"res\", it only holds the results of instructions"
+            ),
+            CpuError::InvalidDestination(value) => write!(
+                f,
+                "Cannot write to the value {value}, must be a register or a memory slot"
+            ),
+            CpuError::SignedValueOutOfRange(value) => {
+                write!(f, "The signed value {value} doesn't fit in a word")
+            }
+            CpuError::JumpOutOfBounds(address) => {
+                write!(
+                    f,
+                    "Cannot jump to address {address}, it is outside of the program"
+                )
+            }
+            CpuError::NotAFunction(address) => {
+                write!(f, "Cannot call address {address}, it is not a function")
+            }
+            CpuError::UnknownFunction(fn_name) => write!(f, "Function \"{fn_name}\" not found"),
+            CpuError::StackOverflow { depth, fn_name } => write!(
+                f,
+                "Stack overflow when calling function \"{fn_name}\" at call depth {depth}"
+            ),
+            CpuError::Io(error) => write!(f, "Cannot write output: {error}"),
+            CpuError::NoInput => write!(f, "No valid input value to read"),
+            CpuError::FrameCorrupted { depth, fn_name } => write!(
+                f,
+                "The sub stack of function \"{fn_name}\" at call depth {depth} was corrupted"
+            ),
+            CpuError::AlreadyStarted => write!(
+                f,
+                "The program was already started, a paused CPU continues with resume"
+            ),
+            CpuError::UnknownSyscall(number) => write!(f, "No handler for syscall {number}"),
+            CpuError::UnhandledInterrupt(number) => write!(f, "No handler for interrupt {number}"),
+            CpuError::IllegalInstruction(code) => {
+                write!(f, "Illegal instruction, no handler for opcode {code}")
+            }
+            CpuError::AssertionFailed { actual, expected } => {
+                write!(f, "Assertion failed, expected {expected} but got {actual}")
+            }
+            CpuError::DuplicateFunction {
+                name,
+                first_address,
+                second_address,
+            } => write!(
+                f,
+                "Function \"{name}\" is declared twice, at addresses {first_address} and {second_address}"
+            ),
+            CpuError::OverlappingDevice { start, length } => write!(
+                f,
+                "Cannot map a device to {length} addresses from {start}, another device already uses some of them"
+            ),
+            CpuError::NotAMemoryLocation => {
+                write!(f, "Expected a memory argument, not a register or a value")
+            }
+            CpuError::RangeOutOfBounds { address, length } => write!(
+                f,
+                "The range of {length} slots from address {address} goes past the last address"
+            ),
+            CpuError::DataOutOfBounds { address, size } => write!(
+                f,
+                "Address {address} is out of bounds, the data segment only has {size} slots"
+            ),
+            CpuError::WriteToReadOnly(address) => write!(
+                f,
+                "Cannot write to address {address} of the data segment, it is read-only"
+            ),
+            CpuError::Memory(error) => write!(f, "{error}"),
+            CpuError::Backtraced { error, backtrace } => write!(f, "{error}\n{backtrace}"),
+        }
+    }
+}
+
+/** The registers programs can use, see `CpuRegisters` */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /** General-use registers, laid out first so that they index `CpuRegisters::general` */
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Res,
+    Flags,
+    /** Read-only */
+    Cycles,
+    /** Read-only, the address of the instruction reading it */
+    Ip,
+    /** Read-only, the number of sub stacks, 1 inside "main" */
+    Sp,
+}
+impl Register {
+    /** The names of the first four general-use registers, from before there were sixteen of them */
+    pub const A: Register = Register::R0;
+    pub const B: Register = Register::R1;
+    pub const C: Register = Register::R2;
+    pub const D: Register = Register::R3;
+
+    /** Every register, in the order of their names */
+    pub const ALL: [Register; 21] = [
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::R3,
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::R7,
+        Register::R8,
+        Register::R9,
+        Register::R10,
+        Register::R11,
+        Register::R12,
+        Register::R13,
+        Register::R14,
+        Register::R15,
+        Register::Res,
+        Register::Flags,
+        Register::Cycles,
+        Register::Ip,
+        Register::Sp,
+    ];
+    pub const NAMES: [&str; 21] = [
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "r13",
+        "r14", "r15", "res", "flags", "cycles", "ip", "sp",
+    ];
+    /** The other names registers can be referred to by */
+    pub const ALIASES: [(&str, Register); 4] = [
+        ("a", Register::A),
+        ("b", Register::B),
+        ("c", Register::C),
+        ("d", Register::D),
+    ];
+
+    /** The name of the register in program listings */
+    pub fn name(self) -> &'static str {
+        Register::NAMES[self as usize]
+    }
+}
+impl std::str::FromStr for Register {
+    type Err = CpuError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Register::ALL
+            .into_iter()
+            .find(|register| register.name() == name)
+            .or_else(|| {
+                Register::ALIASES
+                    .into_iter()
+                    .find(|&(alias, _)| alias == name)
+                    .map(|(_, register)| register)
+            })
+            .ok_or_else(|| CpuError::UnknownRegister(name.to_owned()))
+    }
+}
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/** The float registers, see `CpuRegisters` */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatRegister {
+    /** Laid out first so that they index `CpuRegisters::float` */
+    F0,
+    F1,
+    F2,
+    F3,
+    FRes,
+}
+impl FloatRegister {
+    pub const ALL: [FloatRegister; 5] = [
+        FloatRegister::F0,
+        FloatRegister::F1,
+        FloatRegister::F2,
+        FloatRegister::F3,
+        FloatRegister::FRes,
+    ];
+    pub const NAMES: [&str; 5] = ["f0", "f1", "f2", "f3", "fres"];
+
+    /** The name of the register in program listings */
+    pub fn name(self) -> &'static str {
+        FloatRegister::NAMES[self as usize]
+    }
+}
+impl std::str::FromStr for FloatRegister {
+    type Err = CpuError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        FloatRegister::ALL
+            .into_iter()
+            .find(|register| register.name() == name)
+            .ok_or_else(|| CpuError::UnknownRegister(name.to_owned()))
+    }
+}
+impl std::fmt::Display for FloatRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/** Where an instruction reads or writes a value */
+#[derive(Clone, Copy, Debug)]
+pub enum InstructionArgument<W: Word = u16> {
+    /* Get a value from an address in the current sub stack */
+    Stack(W),
+    /* Get a value from a register */
+    Register(Register),
+    /* Get a float from a float register, only float instructions and MOV accept it */
+    FloatRegister(FloatRegister),
+    /* Get a value from the address in the current sub stack held by a register */
+    Indirect(Register),
+    /* Get a value from the address in the current sub stack held by a register (base) plus an offset,
+    the sum wraps around past the last address */
+    StackOffset { base: Register, offset: W },
+    /* Get a value from an address in the global memory, shared by every function */
+    Global(W),
+    /* Get a value from an address in the sub stack of the function that called the current one */
+    CallerStack(W),
+    /* Get a value from an address in the read-only data segment */
+    Data(W),
+    /* A hard-coded value */
+    Value(W),
+    /* A hard-coded negative (or positive) value, used as its two's complement bit pattern in the word */
+    SignedValue(i16),
+}
+
+/** A memory slot designated by an argument, once its address is computed */
+#[derive(Clone, Copy)]
+enum MemoryLocation<W: Word> {
+    Stack(W),
+    Global(W),
+    CallerStack(W),
+    Data(W),
+}
+impl<W: Word> MemoryLocation<W> {
+    fn address(self) -> W {
+        match self {
+            MemoryLocation::Stack(address)
+            | MemoryLocation::Global(address)
+            | MemoryLocation::CallerStack(address)
+            | MemoryLocation::Data(address) => address,
+        }
+    }
+
+    /** The slot `offset` addresses further in the same memory, None past the last address */
+    fn offset(self, offset: W) -> Option<MemoryLocation<W>> {
+        let address = self.address().checked_add(offset)?;
+        Some(match self {
+            MemoryLocation::Stack(_) => MemoryLocation::Stack(address),
+            MemoryLocation::Global(_) => MemoryLocation::Global(address),
+            MemoryLocation::CallerStack(_) => MemoryLocation::CallerStack(address),
+            MemoryLocation::Data(_) => MemoryLocation::Data(address),
+        })
+    }
+
+    /** The argument designating the slot directly, whatever registers its address was computed from */
+    fn argument(self) -> InstructionArgument<W> {
+        match self {
+            MemoryLocation::Stack(address) => InstructionArgument::Stack(address),
+            MemoryLocation::Global(address) => InstructionArgument::Global(address),
+            MemoryLocation::CallerStack(address) => InstructionArgument::CallerStack(address),
+            MemoryLocation::Data(address) => InstructionArgument::Data(address),
+        }
+    }
+}
+
+/** The rest of a block left when one of its instructions called a function or paused the CPU,
+executed as one more instruction once the CPU is back at the depth of the block */
+#[derive(Clone, Debug)]
+struct BlockContinuation<W: Word> {
+    /** The call depth the block runs at */
+    depth: usize,
+    /** The address of the instruction holding the block */
+    address: u16,
+    instructions: Vec<CpuInstruction<W>>,
+}
+
+#[derive(Clone, Debug)]
+/** Everytime a whole instruction is completed,
+its result will be pushed to the "res" register.
+Function and label names are `Name`s, string literals and strings built at runtime both turn into one with `into()`.
+Values are words of the CPU running the instruction, while instruction addresses and opcodes stay 16-bit */
+pub enum CpuInstruction<W: Word = u16> {
+    /** ADD instruction | reg/value + reg/value |
+    The sum wraps around on overflow (it is computed modulo 2 to the power of the word size) and sets the carry flag,
+    unless the CPU traps on overflow */
+    Add(InstructionArgument<W>, InstructionArgument<W>),
+    /** SUB instruction | reg/value - reg/value |
+    The difference wraps around below 0 (it is computed modulo 2 to the power of the word size) and sets the carry flag,
+    unless the CPU traps on overflow */
+    Sub(InstructionArgument<W>, InstructionArgument<W>),
+    /** ADC instruction | reg/value + reg/value + carry |
+    Adds the carry flag left by the previous instruction, used to chain additions over several words */
+    Adc(InstructionArgument<W>, InstructionArgument<W>),
+    /** SBB instruction | reg/value - reg/value - carry |
+    Subtracts the carry (borrow) flag left by the previous instruction, used to chain subtractions over several words */
+    Sbb(InstructionArgument<W>, InstructionArgument<W>),
+    /** SATADD instruction | reg/value + reg/value |
+    The sum is clamped to the largest word instead of wrapping around, which sets the carry flag */
+    SatAdd(InstructionArgument<W>, InstructionArgument<W>),
+    /** SATSUB instruction | reg/value - reg/value |
+    The difference is clamped to 0 instead of wrapping around, which sets the carry flag */
+    SatSub(InstructionArgument<W>, InstructionArgument<W>),
+    /** MUL instruction | reg/value * reg/value |
+    The product wraps around on overflow (it is computed modulo 2 to the power of the word size) and sets the carry flag,
+    unless the CPU traps on overflow */
+    Mul(InstructionArgument<W>, InstructionArgument<W>),
+    /** MULWIDE instruction | reg/value * reg/value |
+    Computes the full product on two words, the low word goes to "res" and the high word goes to "d" */
+    MulWide(InstructionArgument<W>, InstructionArgument<W>),
+    /** ADD32 instruction | (a, b) + (c, d) -> (a, b) |
+    Adds the double-word values (32-bit for 16-bit words) held by the register pairs, wrapping around on overflow.
+    Sets the flags from the double-word result and leaves "res" untouched */
+    Add32(),
+    /** SUB32 instruction | (a, b) - (c, d) -> (a, b) |
+    Subtracts the double-word values (32-bit for 16-bit words) held by the register pairs, wrapping around below 0.
+    Sets the flags from the double-word result and leaves "res" untouched */
+    Sub32(),
+    /** DIV instruction | reg/value / reg/value |
+    The quotient is rounded towards zero, dividing by 0 stops the CPU with an error */
+    Div(InstructionArgument<W>, InstructionArgument<W>),
+    /** MOD instruction | reg/value % reg/value |
+    Computes the remainder of the division, dividing by 0 stops the CPU with an error */
+    Mod(InstructionArgument<W>, InstructionArgument<W>),
+
+    /** NEG instruction | -reg/value |
+    Two's complement negation, NEG 0 gives 0 */
+    Neg(InstructionArgument<W>),
+    /** ABS instruction | |reg/value| |
+    Absolute value of the value read as a signed integer.
+    The lowest one (0x8000 for 16-bit words) has no positive counterpart and wraps around to itself */
+    Abs(InstructionArgument<W>),
+
+    /** AND instruction | reg/value & reg/value */
+    And(InstructionArgument<W>, InstructionArgument<W>),
+    /** OR instruction | reg/value | reg/value */
+    Or(InstructionArgument<W>, InstructionArgument<W>),
+    /** XOR instruction | reg/value ^ reg/value */
+    Xor(InstructionArgument<W>, InstructionArgument<W>),
+    /** NOT instruction | !reg/value | Inverts all the bits of the value */
+    Not(InstructionArgument<W>),
+    /** SHL instruction | reg/value << reg/value |
+    Shifting by the word size or more moves every bit out and gives 0 */
+    Shl(InstructionArgument<W>, InstructionArgument<W>),
+    /** SHR instruction | reg/value >> reg/value |
+    Logical shift, shifting by the word size or more moves every bit out and gives 0 */
+    Shr(InstructionArgument<W>, InstructionArgument<W>),
+    /** ROL instruction | reg/value rotated left by reg/value |
+    Bits shifted out on the left come back on the right, the amount is taken modulo the word size */
+    Rol(InstructionArgument<W>, InstructionArgument<W>),
+    /** ROR instruction | reg/value rotated right by reg/value |
+    Bits shifted out on the right come back on the left, the amount is taken modulo the word size */
+    Ror(InstructionArgument<W>, InstructionArgument<W>),
+    /** POPCOUNT instruction | Counts the bits set to 1 in the value */
+    Popcount(InstructionArgument<W>),
+    /** CLZ instruction | Counts the leading zero bits of the value, 0 gives the word size */
+    Clz(InstructionArgument<W>),
+    /** CTZ instruction | Counts the trailing zero bits of the value, 0 gives the word size */
+    Ctz(InstructionArgument<W>),
+    /** SEXT8 instruction | Sign-extends the low 8 bits of the value to the whole word */
+    Sext8(InstructionArgument<W>),
+    /** ZEXT8 instruction | Zero-extends the low 8 bits of the value to the whole word */
+    Zext8(InstructionArgument<W>),
+    /** SETBIT instruction | reg |= 1 << reg/value |
+    Sets a bit of a register or stack value in place and copies the result to "res",
+    the bit index is taken modulo the word size */
+    SetBit(InstructionArgument<W>, InstructionArgument<W>),
+    /** CLEARBIT instruction | reg &= !(1 << reg/value) |
+    Clears a bit of a register or stack value in place and copies the result to "res",
+    the bit index is taken modulo the word size */
+    ClearBit(InstructionArgument<W>, InstructionArgument<W>),
+    /** TESTBIT instruction | (reg/value >> reg/value) & 1 |
+    Returns 1 if the bit is set, 0 otherwise, the bit index is taken modulo the word size */
+    TestBit(InstructionArgument<W>, InstructionArgument<W>),
+    /** MOV instruction | reg/value -> reg |
+    Moves the first value (or register's content) into another register */
+    Mov(InstructionArgument<W>, InstructionArgument<W>),
+    /** INC instruction | reg += 1 |
+    Increments a register or stack value in place (wrapping around) and copies the result to "res" */
+    Inc(InstructionArgument<W>),
+    /** DEC instruction | reg -= 1 |
+    Decrements a register or stack value in place (wrapping around) and copies the result to "res" */
+    Dec(InstructionArgument<W>),
+    /** SWAP instruction | reg <-> reg |
+    Exchanges the contents of two registers or stack values */
+    Swap(InstructionArgument<W>, InstructionArgument<W>),
+
+    /** PUSH instruction | Appends the value at the end of the current sub stack |
+    Sub stacks of functions declaring their size cannot grow, pushing to them stops the CPU with an error */
+    Push(InstructionArgument<W>),
+    /** POP instruction | Removes the last value of the current sub stack and moves it into a register |
+    Popping from an empty sub stack stops the CPU with an error */
+    Pop(InstructionArgument<W>),
+    /** RESERVE instruction | Grows the current sub stack with 0s until it holds at least reg/value slots |
+    Reading a slot never grows the sub stack, this makes room for PUSH and POP to work past it */
+    Reserve(InstructionArgument<W>),
+    /** MEMCOPY instruction | Copies `length` (third) slots from the memory argument `source` (second)
+    to the memory argument `destination` (first) |
+    Overlapping ranges are copied as if through a temporary buffer, and a range going out of bounds
+    or beyond the memory limits stops the CPU with an error before anything is copied */
+    MemCopy(
+        InstructionArgument<W>,
+        InstructionArgument<W>,
+        InstructionArgument<W>,
+    ),
+    /** MEMSET instruction | Writes reg/value (second) into `length` (third) slots from the memory argument
+    `destination` (first) | It lasts a single cycle whatever the length, and a range going out of bounds
+    or beyond the memory limits stops the CPU with an error before anything is written */
+    MemSet(
+        InstructionArgument<W>,
+        InstructionArgument<W>,
+        InstructionArgument<W>,
+    ),
+    /** ALLOC instruction | Allocates a heap block of reg/value slots and returns its global address |
+    The block is filled with 0s and is accessed with global arguments, a full heap stops the CPU with an error */
+    Alloc(InstructionArgument<W>),
+    /** FREE instruction | Releases the heap block starting at the global address reg/value |
+    Freeing an address that isn't an allocated block, or freeing it twice, stops the CPU with an error */
+    Free(InstructionArgument<W>),
+
+    /** EQ instruction | reg/value == reg/value |
+    Compares the two values and returns 0 if the comparison is false, 1 if it's true */
+    Eq(InstructionArgument<W>, InstructionArgument<W>),
+    /** NEQ instruction | reg/value != reg/value |
+    Compares the two values and returns 1 if they differ, 0 if they are equal */
+    Neq(InstructionArgument<W>, InstructionArgument<W>),
+    /** LT instruction | reg/value < reg/value |
+    Returns 1 if the first value is lower than the second one, 0 otherwise */
+    Lt(InstructionArgument<W>, InstructionArgument<W>),
+    /** GT instruction | reg/value > reg/value |
+    Returns 1 if the first value is greater than the second one, 0 otherwise */
+    Gt(InstructionArgument<W>, InstructionArgument<W>),
+    /** LE instruction | reg/value <= reg/value |
+    Returns 1 if the first value is lower than or equal to the second one, 0 otherwise */
+    Le(InstructionArgument<W>, InstructionArgument<W>),
+    /** GE instruction | reg/value >= reg/value |
+    Returns 1 if the first value is greater than or equal to the second one, 0 otherwise */
+    Ge(InstructionArgument<W>, InstructionArgument<W>),
+
+    /** SLT instruction | reg/value < reg/value |
+    Same as LT, but both values are read as signed (two's complement) integers */
+    Slt(InstructionArgument<W>, InstructionArgument<W>),
+    /** SGT instruction | reg/value > reg/value |
+    Same as GT, but both values are read as signed (two's complement) integers */
+    Sgt(InstructionArgument<W>, InstructionArgument<W>),
+    /** SLE instruction | reg/value <= reg/value |
+    Same as LE, but both values are read as signed (two's complement) integers */
+    Sle(InstructionArgument<W>, InstructionArgument<W>),
+    /** SGE instruction | reg/value >= reg/value |
+    Same as GE, but both values are read as signed (two's complement) integers */
+    Sge(InstructionArgument<W>, InstructionArgument<W>),
+    /** MIN instruction | Returns the lowest of the two values */
+    Min(InstructionArgument<W>, InstructionArgument<W>),
+    /** MAX instruction | Returns the greatest of the two values */
+    Max(InstructionArgument<W>, InstructionArgument<W>),
+    /** CMP instruction | reg/value <=> reg/value |
+    Compares the two values and stores the outcome in the "flags" register, leaving "res" untouched */
+    Cmp(InstructionArgument<W>, InstructionArgument<W>),
+
+    /** FADD instruction | freg + freg -> fres |
+    Follows IEEE 754: an infinite or NaN operand gives an infinite or NaN sum, and so does a sum too large for a float */
+    FAdd(InstructionArgument<W>, InstructionArgument<W>),
+    /** FSUB instruction | freg - freg -> fres |
+    Follows IEEE 754 like FADD */
+    FSub(InstructionArgument<W>, InstructionArgument<W>),
+    /** FMUL instruction | freg * freg -> fres |
+    Follows IEEE 754 like FADD */
+    FMul(InstructionArgument<W>, InstructionArgument<W>),
+    /** FDIV instruction | freg / freg -> fres |
+    Unlike DIV, dividing by 0 doesn't stop the CPU: it gives an infinity with the sign of the quotient,
+    or NaN when dividing 0 by 0 */
+    FDiv(InstructionArgument<W>, InstructionArgument<W>),
+    /** INTTOFLOAT instruction | reg/value -> fres |
+    Converts the unsigned value to the nearest float, which can lose precision beyond 24 bits */
+    IntToFloat(InstructionArgument<W>),
+    /** FLOATTOINT instruction | freg -> res |
+    Converts the float to an unsigned value, rounding towards zero. NaN gives 0, and the floats out of range
+    are clamped to 0 or to the largest word, which sets the carry flag */
+    FloatToInt(InstructionArgument<W>),
+
+    /** FN function | Declares a function and the number of stack slots it uses.
+    Does nothing when actually executed |
+    Calling the function pre-allocates that many zeroed slots in its sub stack,
+    and accessing a slot beyond them stops the CPU with an error.
+    0 doesn't declare a size and lets the sub stack grow on demand */
+    Fn(Name, W),
+    /** RET instruction | Returns from the current function */
+    Ret(),
+    /** RETVAL instruction | Returns from the current function with a value |
+    The value is read in the callee's sub stack and stored in "res" before returning, the caller finds it there */
+    RetVal(InstructionArgument<W>),
+    /** CALL instruction | Calls a function |
+    When the program doesn't declare it, calls the host function registered under that name */
+    Call(Name),
+    /** CALLIF instruction | IF reg/value >= 1 then call the function, ELSE continue normally |
+    Either way, execution continues at the instruction following the CALLIF */
+    CallIf(InstructionArgument<W>, Name),
+    /** CALLARGS instruction | Calls a function with arguments |
+    The arguments are read in the caller's sub stack and copied to the slots 0..N of the callee's sub stack */
+    CallArgs(Name, Vec<InstructionArgument<W>>),
+    /** CALLINDIRECT instruction | Calls the function declared at the address held by reg/value |
+    The address must be the one of a FN instruction, as returned by LOADFNADDR */
+    CallIndirect(InstructionArgument<W>),
+    /** TAILCALL instruction | Calls a function by replacing the current sub stack instead of adding one |
+    The callee returns directly to the caller of the current function,
+    so tail-recursive functions run in constant memory */
+    TailCall(Name),
+    /** INT instruction | Raises the interrupt reg/value, calling its handler function like CALL |
+    The handler runs with interrupts disabled and returns with RET, which restores them,
+    an interrupt without a handler stops the CPU with an error */
+    Int(InstructionArgument<W>),
+    /** CLI instruction | Disables interrupts | A timer firing meanwhile is delivered after STI */
+    Cli(),
+    /** STI instruction | Enables interrupts | INT instructions run their handler either way */
+    Sti(),
+    /** LOADFNADDR instruction | Returns the address of a function, to be used with CALLINDIRECT |
+    Only the low bits of the address that fit in a word are kept */
+    LoadFnAddr(Name),
+
+    /** GOTO instruction | Jumps to the instruction at the provided address and executes it
+    Use with caution, it is powerful but can have side-effects
+    or can lead to undefined behavior. An address outside of the program stops the CPU with an error */
+    Goto(u16),
+    /** GOTOIF instruction | IF reg/value >= 1 then execute the instruction at the provided address next,
+    ELSE continue normally | The same as IF reg/value GOTO address ELSE NOP, in a single instruction */
+    GotoIf(InstructionArgument<W>, u16),
+    /** LOOP instruction | Decrements a register or stack value in place,
+    then jumps to the provided label if it is not 0, otherwise continues normally |
+    The counter wraps around when it is already 0, and "res" is left untouched */
+    Loop(InstructionArgument<W>, Name),
+    /** SWITCH instruction | Executes the instruction at the address found at index reg/value of the table next |
+    When the index is outside of the table, continues normally so a default case can follow the SWITCH */
+    Switch(InstructionArgument<W>, Vec<u16>),
+    /** LABEL instruction | Declares a label that GOTOLABEL can jump to. Does nothing when actually executed */
+    Label(Name),
+    /** GOTOLABEL instruction | Jumps to the provided label, like the taken branch of JZ/JNZ |
+    Unlike GOTO, the target stays correct when instructions are inserted before it */
+    GotoLabel(Name),
+    /** JUMP instruction | Jumps by a signed offset relative to this instruction and executes the target |
+    JUMP 1 continues normally, JUMP -1 executes the previous instruction next */
+    Jump(i16),
+    /** JZ instruction | Jumps to the provided label if reg/value is 0, otherwise continues normally */
+    Jz(InstructionArgument<W>, Name),
+    /** JNZ instruction | Jumps to the provided label if reg/value is not 0, otherwise continues normally */
+    Jnz(InstructionArgument<W>, Name),
+    /** IF instruction |
+    IF reg/value >= 1 then execute the first instruction, ELSE execute the second fall-back instruction */
+    If(
+        InstructionArgument<W>,
+        Box<CpuInstruction<W>>,
+        Box<CpuInstruction<W>>,
+    ),
+    /** IFTHEN instruction |
+    IF reg/value >= 1 then execute the instruction, ELSE continue normally */
+    IfThen(InstructionArgument<W>, Box<CpuInstruction<W>>),
+    /** IFBLOCK instruction |
+    IF reg/value >= 1 then execute the first block of instructions, ELSE execute the second one.
+    The whole block runs within this instruction, but a jump (GOTO and the other jumps, RET, RETVAL, TAILCALL)
+    or stopping the CPU ends the block early. A function called from a block (CALL and the other calls, INT,
+    the fault handler) returns into it, and a BRK pauses it: the rest of the block runs once the function returns
+    or the CPU resumes, as one more instruction at the address of the IFBLOCK */
+    IfBlock(
+        InstructionArgument<W>,
+        Vec<CpuInstruction<W>>,
+        Vec<CpuInstruction<W>>,
+    ),
+
+    /** WAIT instruction | Does nothing for reg/value cycles, WAIT 0 is the same as NOP */
+    Wait(InstructionArgument<W>),
+    /** NOP instruction | Does nothing for one cycle, it doesn't even touch "res" */
+    Nop(),
+
+    /** RAND instruction | Returns a random value lower than reg/value, 0 allows any word |
+    The values come from the CPU's seedable generator */
+    Rand(InstructionArgument<W>),
+
+    /** PRINT instruction | Writes reg/value in decimal followed by a new line to the CPU's output */
+    Print(InstructionArgument<W>),
+    /** PRINTCHAR instruction | Writes the low 8 bits of reg/value as a single byte to the CPU's output |
+    The high 8 bits are ignored */
+    PrintChar(InstructionArgument<W>),
+    /** PRINTSTR instruction | Writes the low 8 bits of `length` (second) consecutive values to the CPU's output,
+    starting at `start` (first) | A memory argument, such as a string of the data segment, is the first value itself,
+    a register or a value holds the address of the first value in the current sub stack. Registers are left untouched */
+    PrintStr(InstructionArgument<W>, InstructionArgument<W>),
+    /** READ instruction | Reads a value from the CPU's input and moves it into a register |
+    Running out of input, or reading a number that doesn't fit in a word, stops the CPU with an error */
+    Read(InstructionArgument<W>),
+    /** SYSCALL instruction | Runs the host handler registered for the syscall number reg/value |
+    The handler has access to the registers and memory, an unregistered number stops the CPU with an error */
+    Syscall(InstructionArgument<W>),
+    /** CUSTOM instruction | Runs the host handler registered for the opcode (first) with the values of the arguments |
+    The handler has access to the registers and the current sub stack, the value it returns is moved into "res",
+    an unregistered opcode stops the CPU with an illegal instruction error */
+    Custom(u16, Vec<InstructionArgument<W>>),
+    /** BRK instruction | Pauses the CPU and hands control back to the host |
+    Once resumed, execution continues at the following instruction */
+    Brk(),
+    /** ASSERT instruction | reg/value == reg/value |
+    Does nothing if the actual value (first) equals the expected one (second),
+    otherwise stops the CPU with an assertion failure */
+    Assert(InstructionArgument<W>, InstructionArgument<W>),
+    /** HALT instruction | Stops the CPU immediately with the error code reg/value |
+    Nothing else is executed, not even the rest of an IFBLOCK */
+    Halt(InstructionArgument<W>),
+    /** EXIT instruction | Sets the status of the CPU to "exiting" */
+    Exit(),
+}
+
+/** Set in "flags" when the compared values are equal, or when an arithmetic result is 0 */
+pub const FLAG_ZERO: u16 = 1 << 0;
+/** Set in "flags" when the first compared value is lower than the second one read as signed values,
+or when the highest bit of an arithmetic result is set */
+pub const FLAG_NEGATIVE: u16 = 1 << 1;
+/** Set in "flags" when the first compared value is lower than the second one read as unsigned values,
+or when an arithmetic instruction carried out of (or borrowed past) the word */
+pub const FLAG_CARRY: u16 = 1 << 2;
+
+/** Moved into "res" before calling the fault handler when a DIV or MOD instruction divides by 0 */
+pub const FAULT_DIVISION_BY_ZERO: u16 = 1;
+/** Moved into "res" before calling the fault handler when an arithmetic instruction overflows while trapping */
+pub const FAULT_OVERFLOW: u16 = 2;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/**
+    r0 to r15 = general-use registers, r0 to r3 can also be named a, b, c and d,
+    "d" also receives the high half of the result of MULWIDE.
+    They are shared by every function, calls don't save or restore them
+
+    (a, b) and (c, d) = double-word register pairs used by ADD32 and SUB32,
+    the first register of a pair holds the high word and the second one the low word
+
+    res = used to store the result of the last instruction
+
+    flags = set by comparisons and arithmetic instructions,
+    bit 0 is zero, bit 1 is negative and bit 2 is carry
+
+    f0 to f3 = float registers, 32-bit whatever the word size
+
+    fres = used to store the result of the last float instruction
+*/
+pub struct CpuRegisters<W: Word = u16> {
+    pub general: [W; 16],
+    pub res: W,
+    pub flags: W,
+    pub float: [f32; 4],
+    pub fres: f32,
+}
+
+/** Where the CPU is in the execution of its program */
+#[derive(Clone, Debug)]
+pub enum CpuStatus<W: Word = u16> {
+    /** Neither `execute` nor `start` was called yet */
+    NotStarted,
+    /** The program is running, or ready to run with `step` */
+    Running,
+    /** An EXIT instruction stopped the CPU */
+    Exiting,
+    /** A BRK instruction paused the CPU, execution can continue with `resume` */
+    Paused,
+    /** A HALT instruction stopped the CPU */
+    Halted { code: W, address: u16 },
+    /** The CPU stopped because of an error */
+    Failed(CpuError),
+}
+/** A host function a program can run with the SYSCALL instruction */
+pub type SyscallHandler<W = u16> = Box<dyn FnMut(&mut CpuRegisters<W>, &mut MemoryState<W>)>;
+
+/** The handler of a CUSTOM opcode, it receives the values of the arguments and returns the value for "res" */
+pub type CustomOpHandler<W = u16> =
+    Box<dyn FnMut(&[W], &mut CpuRegisters<W>, &mut SubStack<W>) -> W>;
+
+/** What a host function can access while it runs */
+#[derive(Debug)]
+pub struct HostContext<'a, W: Word = u16> {
+    pub registers: &'a mut CpuRegisters<W>,
+    pub memory: &'a mut MemoryState<W>,
+    /** The arguments of a CALLARGS instruction, empty for other calls */
+    pub arguments: &'a [W],
+}
+
+/** A host function a program can call by name, the value it returns is stored in "res" */
+pub type HostFunction<W = u16> = Box<dyn FnMut(&mut HostContext<W>) -> Option<W>>;
+
+/** Called when a watched register or memory slot is written, with the old value, the new value
+and the address of the instruction writing it */
+pub type WatchCallback<W = u16> = Box<dyn FnMut(W, W, u16)>;
+
+/** Raises an interrupt every `period` cycles by calling the handler function */
+#[derive(Debug)]
+struct TimerInterrupt {
+    period: u64,
+    fn_name: Name,
+    /** The number of executed cycles at which the timer fires next */
+    next_fire: u64,
+}
+
+/** A copy of the state of a CPU, to put it back later with `restore`.
+The program itself (instructions, functions, labels and data segment), the host handlers,
+the devices and the settings of the CPU are not part of it */
+#[derive(Clone, Debug)]
+pub struct CpuSnapshot<W: Word = u16> {
+    registers: CpuRegisters<W>,
+    instruction_pointer: u16,
+    status: CpuStatus<W>,
+    memory: MemoryState<W>,
+    cycles: u64,
+    instructions: u64,
+    interrupts_enabled: bool,
+    timer_pending: bool,
+    interrupt_frames: Vec<(usize, bool)>,
+    block_continuations: Vec<BlockContinuation<W>>,
+    /** When the timer fires next, if there is one */
+    timer_next_fire: Option<u64>,
+    rng: Rng,
+}
+impl<W: Word> CpuSnapshot<W> {
+    /** The registers at the time of the snapshot */
+    pub fn registers(&self) -> &CpuRegisters<W> {
+        &self.registers
+    }
+
+    /** The sub stacks and the global memory at the time of the snapshot */
+    pub fn memory(&self) -> &MemoryState<W> {
+        &self.memory
+    }
+}
+
+/** A function call in progress, as seen from its sub stack */
+#[derive(Clone, Debug)]
+pub struct FrameInfo {
+    /** The address of the instruction that made the call */
+    pub return_address: u16,
+    /** The number of slots of the sub stack */
+    pub data_length: usize,
+    /** The function containing the instruction that made the call, None for the call to "main" */
+    pub caller: Option<Name>,
+}
+
+/** Where the CPU was when it failed, to find out how the program got there */
+#[derive(Clone, Debug)]
+pub struct Backtrace {
+    /** The address of the instruction that failed */
+    pub address: u16,
+    /** The instruction that failed, as written in the program */
+    pub instruction: String,
+    /** The function containing the instruction that failed */
+    pub function: Option<Name>,
+    /** The function calls in progress, from the current one to the call to "main" */
+    pub frames: Vec<FrameInfo>,
+}
+impl std::fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "  in {} at instruction {}: {}",
+            self.function.as_deref().unwrap_or("?"),
+            self.address,
+            self.instruction
+        )?;
+        for frame in &self.frames {
+            if let Some(caller) = &frame.caller {
+                write!(
+                    f,
+                    "\n  called by {caller} at instruction {}",
+                    frame.return_address
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/** Why the CPU stopped running a program without an error */
+#[derive(Clone, Debug)]
+pub enum ExitReason<W: Word = u16> {
+    /** The program ran out of instructions, usually by returning from "main" */
+    Finished,
+    /** An EXIT instruction stopped the CPU */
+    Exited,
+    /** A HALT instruction stopped the CPU */
+    Halted { code: W, address: u16 },
+    /** A BRK instruction paused the CPU before the instruction at that address */
+    Paused { address: u16 },
+}
+
+/** What `execute` and `resume` report about a program that ran without an error */
+#[derive(Clone, Debug)]
+pub struct ExecutionReport<W: Word = u16> {
+    pub exit: ExitReason<W>,
+    /** The number of instructions executed since the program started */
+    pub instructions: u64,
+    /** The number of cycles executed since the program started */
+    pub cycles: u64,
+    /** How long this run took, without the time spent paused before it */
+    pub duration: std::time::Duration,
+    pub registers: CpuRegisters<W>,
+}
+impl<W: Word> std::fmt::Display for ExecutionReport<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.exit {
+            ExitReason::Finished | ExitReason::Exited => write!(
+                f,
+                "Completed all CPU instructions in {} seconds",
+                self.duration.as_secs_f64()
+            )?,
+            ExitReason::Halted { code, address } => {
+                write!(f, "CPU halted with code {code} at instruction {address}")?
+            }
+            ExitReason::Paused { address } => write!(f, "CPU paused before instruction {address}")?,
+        }
+        write!(
+            f,
+            " ({} instructions, {} cycles)",
+            self.instructions, self.cycles
+        )
+    }
+}
+
+/** A CPU whose registers, memory slots and memory addresses are words of type `W`, 16-bit by default.
+Instruction addresses, custom opcodes and devices stay 16-bit whatever the word size */
+pub struct CpuState<W: Word = u16> {
+    /** The number of cycles per second, 0 runs the CPU as fast as possible */
+    frequency: u16,
+    /** The minimum duration of an instruction cycle */
+    cycle_duration: f64,
+    /** The number of cycles the current instruction lasts */
+    instruction_cycles: u64,
+    /** The number of cycles executed so far. Every instruction lasts one cycle,
+    an IF and the instructions of its branch last a single cycle together,
+    and WAIT lasts as many cycles as it waits.
+    Programs can read its low bits that fit in a word through the read-only "cycles" register */
+    cycles: u64,
+    /** The number of instructions executed so far, an IF counting as a single instruction with its branch */
+    instructions: u64,
+    status: CpuStatus<W>,
+    instruction_cache: Vec<CpuInstruction<W>>,
+    /** The address of the instruction being executed, until it completes.
+    Programs can read it through the read-only "ip" register */
+    instruction_pointer: u16,
+    registers: CpuRegisters<W>,
+    memory: MemoryState<W>,
+    function_table: HashMap<Name, u16>,
+    label_table: HashMap<Name, u16>,
+    /** Set when the current instruction already moved the instruction pointer to the next instruction to execute */
+    jumped: bool,
+    /** Set when the current instruction transferred control: it jumped, called a function, returned from one
+    or raised an interrupt. An IFBLOCK stops there, even when the pointer ends up at the IFBLOCK itself */
+    transferred: bool,
+    /** The blocks waiting for a function they called to return or for the CPU to resume, innermost last */
+    block_continuations: Vec<BlockContinuation<W>>,
+    /** Stop with an error when ADD, SUB, MUL, INC or DEC overflows, instead of wrapping around */
+    pub trap_on_overflow: bool,
+    /** Reject the programs moving values to "res" when loading them, so that "res" can only hold
+    the result of an instruction and not a stale value moved there by the program.
+    It is only read by `append_instructions`, set it before loading the program */
+    pub protect_res: bool,
+    /** The maximum number of nested function calls before stopping with a stack overflow */
+    pub max_call_depth: usize,
+    /** Give every sub stack a canary, and stop with an error when returning from a sub stack
+    whose canary or return address changed since it was created.
+    No instruction can write them, so this only catches changes made by the host
+    through the memory given to syscalls, host functions and custom opcodes */
+    pub guard_frames: bool,
+    /** Where the PRINT instructions and the messages about the CPU are written */
+    output: Box<dyn Write>,
+    /** Where the READ instructions read */
+    input: Box<dyn InputSource>,
+    syscalls: HashMap<W, SyscallHandler<W>>,
+    host_functions: HashMap<Name, HostFunction<W>>,
+    custom_ops: HashMap<u16, CustomOpHandler<W>>,
+    /** Every register watcher, in the order they were registered */
+    register_watchers: Vec<(Register, WatchCallback<W>)>,
+    /** Every watcher of a stack or global address, in the order they were registered */
+    memory_watchers: Vec<(WatchedSlot<W>, WatchCallback<W>)>,
+    /** The name of the function handling each interrupt number */
+    interrupt_table: HashMap<W, Name>,
+    timer: Option<TimerInterrupt>,
+    /** Set when the timer fired while interrupts were disabled, it is delivered once they are enabled again */
+    timer_pending: bool,
+    /** Cleared by CLI and set by STI, interrupt handlers run with interrupts disabled */
+    interrupts_enabled: bool,
+    /** The call depth below which each running interrupt handler was entered,
+    with whether interrupts were enabled before it */
+    interrupt_frames: Vec<(usize, bool)>,
+    /** The function called when an arithmetic instruction faults, None stops the CPU with the error instead */
+    fault_handler: Option<Name>,
+    /** Constants of the program, read-only once the program runs */
+    data_segment: Vec<W>,
+    /** The data the sub stack of "main" starts with */
+    main_frame_data: Vec<W>,
+    /** The address of the instruction calling "main", once the program started */
+    entry_address: Option<u16>,
+    /** Stack accesses inside the range of a device are handled by the device instead of the memory */
+    devices: Vec<MappedDevice>,
+    /** Generates the values of the RAND instructions, seeded from the current time by default */
+    rng: Rng,
+    /** Print the instruction pointer and "res" after every instruction */
+    pub trace: bool,
+    /** Print a summary when the CPU stops, and the error when it fails */
+    pub verbose: bool,
+}
+/** The host handlers, the output and the input can't be printed, they are left out */
+impl<W: Word> std::fmt::Debug for CpuState<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CpuState")
+            .field("frequency", &self.frequency)
+            .field("cycles", &self.cycles)
+            .field("instructions", &self.instructions)
+            .field("status", &self.status)
+            .field("instruction_cache", &self.instruction_cache)
+            .field("instruction_pointer", &self.instruction_pointer)
+            .field("registers", &self.registers)
+            .field("memory", &self.memory)
+            .field("function_table", &self.function_table)
+            .field("label_table", &self.label_table)
+            .field("trap_on_overflow", &self.trap_on_overflow)
+            .field("protect_res", &self.protect_res)
+            .field("max_call_depth", &self.max_call_depth)
+            .field("guard_frames", &self.guard_frames)
+            .field("interrupt_table", &self.interrupt_table)
+            .field("timer", &self.timer)
+            .field("interrupts_enabled", &self.interrupts_enabled)
+            .field("fault_handler", &self.fault_handler)
+            .field("data_segment", &self.data_segment)
+            .field("devices", &self.devices)
+            .field("trace", &self.trace)
+            .field("verbose", &self.verbose)
+            .finish_non_exhaustive()
+    }
+}
+
+/** The CPU with 16-bit words, which programs were written for before the word size could change */
+pub type Cpu16 = CpuState<u16>;
+
+impl<W: Word> CpuState<W> {
+    /** Creates a CPU running `frequency` cycles per second, without any program */
+    pub fn new(frequency: u16) -> CpuState<W> {
+        let mut cpu_state = CpuState {
+            frequency: 0,
+            cycle_duration: 0.,
+            instruction_cycles: 1,
+            cycles: 0,
+            instructions: 0,
+            status: CpuStatus::NotStarted,
+            instruction_cache: vec![],
+            instruction_pointer: 0,
+            registers: Default::default(),
+            memory: MemoryState::default(),
+            function_table: HashMap::new(),
+            label_table: HashMap::new(),
+            jumped: false,
+            transferred: false,
+            block_continuations: vec![],
+            trap_on_overflow: false,
+            protect_res: false,
+            guard_frames: false,
+            max_call_depth: 1024,
+            output: Box::new(std::io::stdout()),
+            input: Box::new(StdinInput),
+            syscalls: HashMap::new(),
+            host_functions: HashMap::new(),
+            custom_ops: HashMap::new(),
+            register_watchers: vec![],
+            memory_watchers: vec![],
+            interrupt_table: HashMap::new(),
+            timer: None,
+            timer_pending: false,
+            interrupts_enabled: true,
+            interrupt_frames: vec![],
+            fault_handler: None,
+            data_segment: vec![],
+            main_frame_data: vec![],
+            entry_address: None,
+            devices: vec![],
+            rng: Rng::from_time(),
+            trace: false,
+            verbose: false,
+        };
+        // Important for consistent pacing of CPU cycles
+        cpu_state.update_frequency(frequency);
+
+        cpu_state
+    }
+
+    /** Creates a CPU writing to the output instead of the standard output */
+    pub fn with_output(frequency: u16, output: Box<dyn Write>) -> CpuState<W> {
+        let mut cpu_state = CpuState::new(frequency);
+        cpu_state.set_output(output);
+
+        cpu_state
+    }
+
+    /** Creates a CPU whose RAND instructions give the same values on every run */
+    pub fn with_rng_seed(frequency: u16, seed: u64) -> CpuState<W> {
+        let mut cpu_state = CpuState::new(frequency);
+        cpu_state.set_rng_seed(seed);
+
+        cpu_state
+    }
+
+    /** Stores the memory of the CPU the way it is best suited for the programs it runs */
+    pub fn with_memory_backing(frequency: u16, backing: MemoryBacking) -> CpuState<W> {
+        let mut cpu_state = CpuState::new(frequency);
+        cpu_state.memory = MemoryState::with_backing(backing);
+
+        cpu_state
+    }
+
+    /** Makes the values of the RAND instructions reproducible */
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /** Makes the PRINT instructions and the messages about the CPU write to the output */
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /** Makes the READ instructions read from the input instead of the standard input */
+    pub fn set_input(&mut self, input: Box<dyn InputSource>) {
+        self.input = input;
+    }
+
+    /** Whether the CPU is running, and how it stopped */
+    pub fn get_status(&self) -> &CpuStatus<W> {
+        &self.status
+    }
+
+    /** The sub stacks and the global memory, to read them between two steps, while paused or once stopped */
+    pub fn memory(&self) -> &MemoryState<W> {
+        &self.memory
+    }
+
+    /** Registers the handler run by the SYSCALL instruction with that number, replacing any previous one */
+    pub fn register_syscall(
+        &mut self,
+        number: W,
+        handler: impl FnMut(&mut CpuRegisters<W>, &mut MemoryState<W>) + 'static,
+    ) {
+        self.syscalls.insert(number, Box::new(handler));
+    }
+
+    /** Registers a host function that CALL instructions reach when the program doesn't
+    declare a function with that name, replacing any previous one */
+    pub fn register_host_fn(
+        &mut self,
+        fn_name: impl Into<Name>,
+        function: impl FnMut(&mut HostContext<W>) -> Option<W> + 'static,
+    ) {
+        self.host_functions
+            .insert(fn_name.into(), Box::new(function));
+    }
+
+    /** Registers the handler run by the CUSTOM instructions with that opcode, replacing any previous one */
+    pub fn register_custom_op(
+        &mut self,
+        code: u16,
+        handler: impl FnMut(&[W], &mut CpuRegisters<W>, &mut SubStack<W>) -> W + 'static,
+    ) {
+        self.custom_ops.insert(code, Box::new(handler));
+    }
+
+    /** Calls the callback whenever an instruction writes the register to which the name refers, or the host
+    writes it with `set_register`. Watchers of the same register are called in the order they were registered.
+    The results instructions store in "res", "flags" or "d" are watched like the writes to register arguments,
+    the registers changed by a syscall, a host function or a custom opcode once it returns.
+    Read-only registers are never written, so watching them gives an error */
+    pub fn watch_register(
+        &mut self,
+        name: &str,
+        callback: impl FnMut(W, W, u16) + 'static,
+    ) -> Result<(), CpuError> {
+        let register = name.parse()?;
+        self.get_register_mut(register)?;
+
+        self.register_watchers.push((register, Box::new(callback)));
+        Ok(())
+    }
+
+    /** Calls the callback whenever an instruction writes the stack address, in the sub stack of whichever
+    function is running or of its caller, including PUSH and the writes of syscalls and host functions.
+    Watchers of the same address are called in the order they were registered.
+    Writing an address mapped to a device and the slots a custom opcode changes directly don't call them */
+    pub fn watch_memory(&mut self, address: W, callback: impl FnMut(W, W, u16) + 'static) {
+        let slot = WatchedSlot::Stack(address);
+        self.memory.watch(slot);
+        self.memory_watchers.push((slot, Box::new(callback)));
+    }
+
+    /** Calls the callback whenever an instruction writes the global address, see `watch_memory` */
+    pub fn watch_global(&mut self, address: W, callback: impl FnMut(W, W, u16) + 'static) {
+        let slot = WatchedSlot::Global(address);
+        self.memory.watch(slot);
+        self.memory_watchers.push((slot, Box::new(callback)));
+    }
+
+    /** Makes the function with that name handle the interrupt number, replacing any previous handler.
+    The function is looked up when the interrupt is raised, so it can be appended later */
+    pub fn set_interrupt_handler(&mut self, number: W, fn_name: impl Into<Name>) {
+        self.interrupt_table.insert(number, fn_name.into());
+    }
+
+    /** Raises the interrupt from the host while the program runs, between two instructions.
+    Its handler runs before the next instruction, which is executed once the handler returns */
+    pub fn raise_interrupt(&mut self, number: W) -> Result<(), CpuError> {
+        let address = self.get_interrupt_handler(number)?;
+        self.enter_interrupt_handler();
+        self.interrupt_function(address)
+    }
+
+    /** The address of the function handling the interrupt number */
+    fn get_interrupt_handler(&self, number: W) -> Result<u16, CpuError> {
+        let fn_name = self
+            .interrupt_table
+            .get(&number)
+            .ok_or(CpuError::UnhandledInterrupt(number.to_u64()))?;
+
+        self.get_function_address(fn_name)
+    }
+
+    /** Disables interrupts until the handler about to be called returns */
+    fn enter_interrupt_handler(&mut self) {
+        self.interrupt_frames
+            .push((self.memory.get_depth(), self.interrupts_enabled));
+        self.interrupts_enabled = false;
+    }
+
+    /** Calls the function with that name every `period_cycles` executed cycles, between two instructions.
+    A period of 0 disables the timer, and a handler lasting longer than the period starves the program */
+    pub fn set_timer_interrupt(&mut self, period_cycles: u64, fn_name: impl Into<Name>) {
+        self.timer = (period_cycles > 0).then(|| TimerInterrupt {
+            period: period_cycles,
+            fn_name: fn_name.into(),
+            next_fire: self.cycles + period_cycles,
+        });
+    }
+
+    /** Enters the timer handler if its period elapsed. While interrupts are disabled,
+    which includes while the handler is still running, the firing stays pending until they are enabled again.
+    At most one firing is pending, the others are dropped */
+    fn poll_timer(&mut self) -> Result<(), CpuError> {
+        let Some(timer) = &mut self.timer else {
+            return Ok(());
+        };
+        if self.cycles >= timer.next_fire {
+            let missed_periods = (self.cycles - timer.next_fire) / timer.period;
+            timer.next_fire += (missed_periods + 1) * timer.period;
+            self.timer_pending = true;
+        }
+        if !self.timer_pending || !self.interrupts_enabled {
+            return Ok(());
+        }
+
+        let fn_name = timer.fn_name.clone();
+        let address = self.get_function_address(&fn_name)?;
+        self.timer_pending = false;
+        self.enter_interrupt_handler();
+        self.interrupt_function(address)
+    }
+
+    /** Makes the function with that name handle division by 0 and trapped overflows, with the fault code in "res".
+    Its RET resumes at the instruction following the faulting one, "res" keeping what the handler left there.
+    A fault in an IFBLOCK resumes at the next instruction of the block */
+    pub fn set_fault_handler(&mut self, fn_name: impl Into<Name>) {
+        self.fault_handler = Some(fn_name.into());
+    }
+
+    /** Calls the fault handler for an arithmetic error, other errors and errors without a handler are returned */
+    fn handle_fault(&mut self, error: CpuError) -> Result<(), CpuError> {
+        let code = match error {
+            CpuError::DivisionByZero => FAULT_DIVISION_BY_ZERO,
+            CpuError::Overflow { .. } => FAULT_OVERFLOW,
+            _ => return Err(error),
+        };
+        let Some(fn_name) = &self.fault_handler else {
+            return Err(error);
+        };
+
+        let address = self.get_function_address(fn_name)?;
+        self.set_result(W::from_u64(code.into()));
+        self.call_function(address, vec![])
+    }
+
+    /** Limits the number of slots of a single sub stack, and of all the sub stacks and the global memory together.
+    Growing the memory beyond them stops the CPU with an out of bounds error */
+    pub fn set_memory_limits(&mut self, max_frame_size: usize, max_total_memory: usize) {
+        self.memory.max_frame_size = max_frame_size;
+        self.memory.max_total_memory = max_total_memory;
+    }
+
+    /** Limits the number of rows `dump_memory` writes for each sub stack and for the global memory,
+    the rows after them are replaced by a line counting them */
+    pub fn set_max_dump_rows(&mut self, max_dump_rows: usize) {
+        self.memory.max_dump_rows = max_dump_rows;
+    }
+
+    /** Makes the sub stack of "main" start with the data, from address 0, when the program is executed */
+    pub fn preload_frame(&mut self, data: &[W]) {
+        self.main_frame_data = data.to_vec();
+    }
+
+    /** Writes the data into the global memory from address 0, before executing the program */
+    pub fn preload_global(&mut self, data: &[W]) -> Result<(), CpuError> {
+        for (address, &value) in (0..).zip(data) {
+            self.memory.write_global(W::from_usize(address), value)?;
+        }
+
+        self.notify_memory_watchers();
+        Ok(())
+    }
+
+    /** Adds constants at the end of the data segment and returns the address of the first one */
+    pub fn append_data(&mut self, data: &[W]) -> W {
+        let address = W::from_usize(self.data_segment.len());
+        self.data_segment.extend_from_slice(data);
+
+        address
+    }
+
+    /** Adds the UTF-8 bytes of the string at the end of the data segment, one per slot,
+    and returns the address of the first one along with the number of bytes, ready for PRINTSTR */
+    pub fn append_string(&mut self, string: &str) -> (W, W) {
+        let bytes: Vec<W> = string
+            .bytes()
+            .map(|byte| W::from_u64(byte.into()))
+            .collect();
+
+        (self.append_data(&bytes), W::from_usize(bytes.len()))
+    }
+
+    fn read_data_segment(&self, address: W) -> Result<W, CpuError> {
+        self.data_segment
+            .get(address.to_usize())
+            .copied()
+            .ok_or(CpuError::DataOutOfBounds {
+                address: address.to_u64(),
+                size: self.data_segment.len() as u64,
+            })
+    }
+
+    /** Maps the device to `length` stack addresses from `start`, ranges of devices cannot overlap.
+    Devices read and write 16-bit values, only the low bits that fit in a word reach the program */
+    pub fn map_device(
+        &mut self,
+        start: u16,
+        length: u16,
+        device: Box<dyn Device>,
+    ) -> Result<(), CpuError> {
+        if self
+            .devices
+            .iter()
+            .any(|mapped| mapped.overlaps(start, length))
+        {
+            return Err(CpuError::OverlappingDevice { start, length });
+        }
+
+        self.devices.push(MappedDevice {
+            start,
+            length,
+            device,
+        });
+        Ok(())
+    }
+
+    /** Reads a stack address, or the device mapped to it */
+    fn read_memory(&mut self, address: W) -> Result<W, CpuError> {
+        if let Ok(device_address) = u16::try_from(address.to_u64()) {
+            for mapped in &mut self.devices {
+                if let Some(offset) = mapped.offset_of(device_address) {
+                    return Ok(W::from_u64(mapped.device.read(offset).into()));
+                }
+            }
+        }
+
+        Ok(self.memory.read_data(address)?)
+    }
+
+    /** Writes a stack address, or the device mapped to it */
+    fn write_memory(&mut self, address: W, value: W) -> Result<(), CpuError> {
+        if let Ok(device_address) = u16::try_from(address.to_u64()) {
+            for mapped in &mut self.devices {
+                if let Some(offset) = mapped.offset_of(device_address) {
+                    mapped.device.write(offset, value.to_u64() as u16);
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(self.memory.write_data(address, value)?)
+    }
+
+    /** Calls the watchers of the slots written since the last call */
+    fn notify_memory_watchers(&mut self) {
+        let address = self.instruction_pointer;
+        for write in self.memory.take_writes() {
+            for (watched, callback) in &mut self.memory_watchers {
+                if *watched == write.slot {
+                    callback(write.old_value, write.new_value, address);
+                }
+            }
+        }
+    }
+
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /** Changes the number of cycles per second, 0 runs the CPU as fast as possible */
+    pub fn update_frequency(&mut self, new_frequency: u16) {
+        self.frequency = new_frequency;
+        self.cycle_duration = if new_frequency == 0 {
+            0.
+        } else {
+            1000. / new_frequency as f64
+        };
+    }
+
+    /** Reads a register the way an instruction does */
+    pub fn get_register(&self, register: Register) -> W {
+        match register {
+            Register::Res => self.registers.res,
+            Register::Flags => self.registers.flags,
+            Register::Cycles => W::from_u64(self.cycles),
+            // The instruction pointer only moves past an instruction once it completed
+            Register::Ip => W::from_u64(self.instruction_pointer.into()),
+            Register::Sp => W::from_usize(self.memory.get_depth()),
+            general => self.registers.general[general as usize],
+        }
+    }
+    fn get_register_mut(&mut self, register: Register) -> Result<&mut W, CpuError> {
+        let register = match register {
+            Register::Res => &mut self.registers.res,
+            Register::Flags => &mut self.registers.flags,
+            Register::Cycles | Register::Ip | Register::Sp => {
+                return Err(CpuError::ReadOnlyRegister(register));
+            }
+            general => &mut self.registers.general[general as usize],
+        };
+
+        Ok(register)
+    }
+
+    pub fn get_float_register(&self, register: FloatRegister) -> f32 {
+        match register {
+            FloatRegister::FRes => self.registers.fres,
+            float => self.registers.float[float as usize],
+        }
+    }
+    fn get_float_register_mut(&mut self, register: FloatRegister) -> &mut f32 {
+        match register {
+            FloatRegister::FRes => &mut self.registers.fres,
+            float => &mut self.registers.float[float as usize],
+        }
+    }
+
+    /** Writes a register by name, before `execute` to pass values to the program or once it stopped.
+    Unknown names and read-only registers give an error */
+    pub fn set_register(&mut self, name: &str, value: W) -> Result<(), CpuError> {
+        self.write_register(name.parse()?, value)
+    }
+
+    /** Writes several registers by name, nothing is written when one of the names gives an error */
+    pub fn set_registers(&mut self, values: &[(&str, W)]) -> Result<(), CpuError> {
+        for &(name, _) in values {
+            self.get_register_mut(name.parse()?)?;
+        }
+
+        values
+            .iter()
+            .try_for_each(|&(name, value)| self.set_register(name, value))
+    }
+
+    /** Writes a register and calls its watchers */
+    fn write_register(&mut self, register: Register, value: W) -> Result<(), CpuError> {
+        self.get_register_mut(register)?;
+        self.store_register(register, value);
+
+        Ok(())
+    }
+
+    /** Writes a register known to be writable and calls its watchers */
+    fn store_register(&mut self, register: Register, value: W) {
+        let old_value = std::mem::replace(
+            self.get_register_mut(register).expect("Writable register"),
+            value,
+        );
+
+        let address = self.instruction_pointer;
+        for (watched, callback) in &mut self.register_watchers {
+            if *watched == register {
+                callback(old_value, value, address);
+            }
+        }
+    }
+
+    /** Stores the result of an instruction in "res" and calls its watchers */
+    fn set_result(&mut self, value: W) {
+        self.store_register(Register::Res, value);
+    }
+
+    /** Calls the watchers of the registers a host handler changed, as handlers write them directly */
+    fn notify_changed_registers(&mut self, before: &CpuRegisters<W>) {
+        let address = self.instruction_pointer;
+        for (watched, callback) in &mut self.register_watchers {
+            let value_in = |registers: &CpuRegisters<W>| match *watched {
+                Register::Res => registers.res,
+                Register::Flags => registers.flags,
+                general => registers.general[general as usize],
+            };
+            let (old_value, value) = (value_in(before), value_in(&self.registers));
+            if old_value != value {
+                callback(old_value, value, address);
+            }
+        }
+    }
+
+    /** Reads a register by name, once the program stopped to get the values it left there */
+    pub fn get_register_by_name(&self, name: &str) -> Result<W, CpuError> {
+        Ok(self.get_register(name.parse()?))
+    }
+
+    /** The functions declared by the instructions, with the addresses they will have once appended.
+    A name already declared by the program or earlier in the instructions gives an error */
+    fn collect_functions(
+        &self,
+        instructions: &[CpuInstruction<W>],
+    ) -> Result<HashMap<Name, u16>, CpuError> {
+        let offset = self.instruction_cache.len();
+        let mut functions = HashMap::new();
+        for (i, instruction) in instructions.iter().enumerate() {
+            if let CpuInstruction::Fn(fn_name, _) = instruction {
+                let address = (offset + i) as u16;
+                let declared = self.function_table.get(fn_name).or(functions.get(fn_name));
+                if let Some(&first_address) = declared {
+                    return Err(CpuError::DuplicateFunction {
+                        name: fn_name.clone(),
+                        first_address,
+                        second_address: address,
+                    });
+                }
+
+                functions.insert(fn_name.clone(), address);
+            }
+        }
+
+        Ok(functions)
+    }
+
+    /** The labels declared by the instructions, with the addresses they will have once appended */
+    fn collect_labels(&self, instructions: &[CpuInstruction<W>]) -> HashMap<Name, u16> {
+        let offset = self.instruction_cache.len();
+        instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instruction)| match instruction {
+                CpuInstruction::Label(label_name) => {
+                    Some((label_name.clone(), (offset + i) as u16))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /** Checks an instruction for mistakes that can be caught before the program runs,
+    `labels` being the labels of the instructions appended along with it and `length` the length
+    of the program once they are */
+    fn validate_instruction(
+        &self,
+        instruction: &CpuInstruction<W>,
+        labels: &HashMap<Name, u16>,
+        length: usize,
+    ) -> Result<(), CpuError> {
+        match instruction {
+            CpuInstruction::GotoLabel(label_name)
+            | CpuInstruction::Jz(_, label_name)
+            | CpuInstruction::Jnz(_, label_name)
+            | CpuInstruction::Loop(_, label_name)
+                if !self.label_table.contains_key(label_name)
+                    && !labels.contains_key(label_name) =>
+            {
+                Err(CpuError::UnknownLabel(label_name.clone()))
+            }
+            CpuInstruction::Mov(_, InstructionArgument::Register(Register::Res))
+                if self.protect_res =>
+            {
+                Err(CpuError::ProtectedResult)
+            }
+            CpuInstruction::Inc(InstructionArgument::Value(value))
+            | CpuInstruction::Dec(InstructionArgument::Value(value)) => {
+                Err(CpuError::InvalidDestination(value.to_u64() as i64))
+            }
+            CpuInstruction::Inc(InstructionArgument::SignedValue(value))
+            | CpuInstruction::Dec(InstructionArgument::SignedValue(value)) => {
+                Err(CpuError::InvalidDestination((*value).into()))
+            }
+            CpuInstruction::Switch(_, table) => table.iter().try_for_each(|&address| {
+                if address as usize >= length {
+                    return Err(CpuError::JumpOutOfBounds(address as i32));
+                }
+
+                Ok(())
+            }),
+            CpuInstruction::If(_, first, second) => {
+                self.validate_instruction(first, labels, length)?;
+                self.validate_instruction(second, labels, length)
+            }
+            CpuInstruction::IfThen(_, instruction) => {
+                self.validate_instruction(instruction, labels, length)
+            }
+            CpuInstruction::IfBlock(_, first, second) => first
+                .iter()
+                .chain(second)
+                .try_for_each(|instruction| self.validate_instruction(instruction, labels, length)),
+            _ => Ok(()),
+        }
+    }
+
+    /** Adds the instructions at the end of the program, after checking them for mistakes
+    that can be caught before the program runs. Nothing is added when they have one */
+    pub fn append_instructions(
+        &mut self,
+        instructions: &[CpuInstruction<W>],
+    ) -> Result<(), CpuError> {
+        let functions = self.collect_functions(instructions)?;
+        let labels = self.collect_labels(instructions);
+        let length = self.instruction_cache.len() + instructions.len();
+        instructions
+            .iter()
+            .try_for_each(|instruction| self.validate_instruction(instruction, &labels, length))?;
+
+        self.function_table.extend(functions);
+        self.label_table.extend(labels);
+        self.instruction_cache.extend_from_slice(instructions);
+        Ok(())
+    }
+
+    /** Computes the memory slot designated by an argument, registers and values don't designate any */
+    fn resolve_location(&self, argument: InstructionArgument<W>) -> Option<MemoryLocation<W>> {
+        match argument {
+            InstructionArgument::Stack(address) => Some(MemoryLocation::Stack(address)),
+            InstructionArgument::Indirect(register) => {
+                Some(MemoryLocation::Stack(self.get_register(register)))
+            }
+            InstructionArgument::StackOffset { base, offset } => Some(MemoryLocation::Stack(
+                self.get_register(base).wrapping_add(offset),
+            )),
+            InstructionArgument::Global(address) => Some(MemoryLocation::Global(address)),
+            InstructionArgument::CallerStack(address) => Some(MemoryLocation::CallerStack(address)),
+            InstructionArgument::Data(address) => Some(MemoryLocation::Data(address)),
+            InstructionArgument::Register(_)
+            | InstructionArgument::FloatRegister(_)
+            | InstructionArgument::Value(_)
+            | InstructionArgument::SignedValue(_) => None,
+        }
+    }
+
+    fn read_location(&mut self, location: MemoryLocation<W>) -> Result<W, CpuError> {
+        match location {
+            MemoryLocation::Stack(address) => self.read_memory(address),
+            MemoryLocation::Global(address) => Ok(self.memory.read_global(address)?),
+            MemoryLocation::CallerStack(address) => Ok(self.memory.read_caller_data(address)?),
+            MemoryLocation::Data(address) => self.read_data_segment(address),
+        }
+    }
+
+    fn write_location(&mut self, location: MemoryLocation<W>, value: W) -> Result<(), CpuError> {
+        match location {
+            MemoryLocation::Stack(address) => self.write_memory(address, value)?,
+            MemoryLocation::Global(address) => self.memory.write_global(address, value)?,
+            MemoryLocation::CallerStack(address) => {
+                self.memory.write_caller_data(address, value)?
+            }
+            MemoryLocation::Data(address) => {
+                return Err(CpuError::WriteToReadOnly(address.to_u64()));
+            }
+        }
+
+        self.notify_memory_watchers();
+        Ok(())
+    }
+
+    /** Checks that `length` slots from the location all exist, before an instruction touches any of them */
+    fn check_range(&self, start: MemoryLocation<W>, length: W) -> Result<(), CpuError> {
+        if length == W::ZERO {
+            return Ok(());
+        }
+        let last = start
+            .offset(length.wrapping_sub(W::ONE))
+            .ok_or(CpuError::RangeOutOfBounds {
+                address: start.address().to_u64(),
+                length: length.to_u64(),
+            })?;
+
+        // The slots before the last one are lower, so they are in bounds when it is
+        if let MemoryLocation::Data(address) = last {
+            return self.read_data_segment(address).map(|_| ());
+        }
+        let size = match last {
+            MemoryLocation::Stack(_) => self.memory.get_current_sub_stack().size,
+            MemoryLocation::Global(_) | MemoryLocation::Data(_) => None,
+            MemoryLocation::CallerStack(_) => {
+                self.memory
+                    .get_parent_sub_stack()
+                    .ok_or(MemoryError::NoCallerFrame)?
+                    .size
+            }
+        };
+        match size {
+            Some(size) if last.address() >= size => {
+                Err(CpuError::Memory(MemoryError::OutOfBounds {
+                    address: last.address().to_u64(),
+                    size: size.to_u64(),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /** Checks that `length` slots from the location can all be written, within the limits of the memory,
+    before an instruction writes any of them */
+    fn check_writable_range(&self, start: MemoryLocation<W>, length: W) -> Result<(), CpuError> {
+        self.check_range(start, length)?;
+
+        match start {
+            MemoryLocation::Stack(address) => {
+                self.memory.check_sub_stack_range(address, length, false)?
+            }
+            MemoryLocation::CallerStack(address) => {
+                self.memory.check_sub_stack_range(address, length, true)?
+            }
+            MemoryLocation::Global(address) => self.memory.check_global_range(address, length)?,
+            MemoryLocation::Data(address) if length > W::ZERO => {
+                return Err(CpuError::WriteToReadOnly(address.to_u64()));
+            }
+            MemoryLocation::Data(_) => {}
+        }
+        Ok(())
+    }
+
+    fn fetch_argument_value(&mut self, argument: InstructionArgument<W>) -> Result<W, CpuError> {
+        let value = match argument {
+            InstructionArgument::Register(register) => self.get_register(register),
+            InstructionArgument::FloatRegister(_) => return Err(CpuError::UnexpectedFloatRegister),
+            InstructionArgument::Value(value) => value,
+            InstructionArgument::SignedValue(value) => {
+                W::from_signed(value.into()).ok_or(CpuError::SignedValueOutOfRange(value))?
+            }
+            _ => {
+                let location = self
+                    .resolve_location(argument)
+                    .ok_or(CpuError::NotAMemoryLocation)?;
+                self.read_location(location)?
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn fetch_float_value(&self, argument: InstructionArgument<W>) -> Result<f32, CpuError> {
+        match argument {
+            InstructionArgument::FloatRegister(register) => Ok(self.get_float_register(register)),
+            _ => Err(CpuError::NotAFloatRegister),
+        }
+    }
+
+    fn set_flags(&mut self, zero: bool, negative: bool, carry: bool) {
+        let mut flags: u16 = 0;
+        if zero {
+            flags |= FLAG_ZERO;
+        }
+        if negative {
+            flags |= FLAG_NEGATIVE;
+        }
+        if carry {
+            flags |= FLAG_CARRY;
+        }
+        self.store_register(Register::Flags, W::from_u64(flags.into()));
+    }
+
+    /** Whether the carry flag is set in "flags" */
+    fn carry_flag(&self) -> bool {
+        self.registers.flags.to_u64() & u64::from(FLAG_CARRY) != 0
+    }
+
+    /** Sets the "flags" register from the result of an arithmetic instruction */
+    fn update_arithmetic_flags(&mut self, result: W, carry: bool) {
+        self.set_flags(result == W::ZERO, result.to_signed() < 0, carry);
+    }
+
+    /** Reads the double-word values held by the (a, b) and (c, d) register pairs */
+    fn read_register_pairs(&self) -> (u64, u64) {
+        let pair = |high: Register, low: Register| {
+            (self.get_register(high).to_u64() << W::BITS) | self.get_register(low).to_u64()
+        };
+
+        (
+            pair(Register::A, Register::B),
+            pair(Register::C, Register::D),
+        )
+    }
+
+    /** Writes the double-word result to the (a, b) register pair and updates "flags" from it,
+    the bits past the double word are dropped */
+    fn write_register_pair(&mut self, result: u64, carry: bool) {
+        let high = W::from_u64(result >> W::BITS);
+        let low = W::from_u64(result);
+        self.store_register(Register::A, high);
+        self.store_register(Register::B, low);
+        self.set_flags(
+            high == W::ZERO && low == W::ZERO,
+            high.to_signed() < 0,
+            carry,
+        );
+    }
+
+    fn get_label_address(&self, label_name: &Name) -> Result<u16, CpuError> {
+        self.label_table
+            .get(label_name)
+            .copied()
+            .ok_or_else(|| CpuError::UnknownLabel(label_name.clone()))
+    }
+
+    /** Moves the instruction pointer so that the instruction at `address` is the next one executed */
+    fn jump_to(&mut self, address: i32) -> Result<(), CpuError> {
+        if address < 0 || address as usize >= self.instruction_cache.len() {
+            return Err(CpuError::JumpOutOfBounds(address));
+        }
+
+        self.instruction_pointer = address as u16;
+        self.jumped = true;
+        self.transferred = true;
+        Ok(())
+    }
+
+    fn check_overflow(&self, overflowed: bool, first: W, second: W) -> Result<(), CpuError> {
+        if overflowed && self.trap_on_overflow {
+            return Err(CpuError::Overflow {
+                first: first.to_u64(),
+                second: second.to_u64(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn write_argument_value(
+        &mut self,
+        argument: InstructionArgument<W>,
+        value: W,
+    ) -> Result<(), CpuError> {
+        match argument {
+            InstructionArgument::Register(register) => self.write_register(register, value)?,
+            InstructionArgument::FloatRegister(_) => return Err(CpuError::UnexpectedFloatRegister),
+            InstructionArgument::Value(value) => {
+                return Err(CpuError::InvalidDestination(value.to_u64() as i64));
+            }
+            InstructionArgument::SignedValue(value) => {
+                return Err(CpuError::InvalidDestination(value.into()));
+            }
+            _ => {
+                let location = self
+                    .resolve_location(argument)
+                    .ok_or(CpuError::NotAMemoryLocation)?;
+                self.write_location(location, value)?;
+            }
+        };
+
+        Ok(())
+    }
+
+    /** Checks that a value can be written to the argument, without writing it */
+    fn check_destination(&mut self, argument: InstructionArgument<W>) -> Result<(), CpuError> {
+        match argument {
+            InstructionArgument::Register(register) => self.get_register_mut(register).map(|_| ()),
+            InstructionArgument::FloatRegister(_) => Err(CpuError::UnexpectedFloatRegister),
+            InstructionArgument::Value(value) => {
+                Err(CpuError::InvalidDestination(value.to_u64() as i64))
+            }
+            InstructionArgument::SignedValue(value) => {
+                Err(CpuError::InvalidDestination(value.into()))
+            }
+            _ => {
+                let location = self
+                    .resolve_location(argument)
+                    .ok_or(CpuError::NotAMemoryLocation)?;
+                self.check_writable_range(location, W::ONE)
+            }
+        }
+    }
+
+    fn get_function_address(&self, fn_name: &Name) -> Result<u16, CpuError> {
+        self.function_table
+            .get(fn_name)
+            .copied()
+            .ok_or_else(|| CpuError::UnknownFunction(fn_name.clone()))
+    }
+
+    /** Returns the name of the function containing the instruction at the address,
+    which is the last function declared before it. None for the instruction calling "main" */
+    fn get_function_at(&self, address: u16) -> Option<Name> {
+        if self.entry_address == Some(address) {
+            return None;
+        }
+
+        self.function_table
+            .iter()
+            .filter(|&(_, &fn_address)| fn_address <= address)
+            .max_by_key(|&(_, &fn_address)| fn_address)
+            .map(|(fn_name, _)| fn_name.clone())
+    }
+
+    /** Describes the function calls in progress, from the current one to the call to "main",
+    it can be read between two steps or while paused */
+    pub fn call_stack(&self) -> Vec<FrameInfo> {
+        self.memory
+            .sub_stacks()
+            .map(|sub_stack| FrameInfo {
+                return_address: sub_stack.return_address,
+                data_length: sub_stack.data.len(),
+                caller: self.get_function_at(sub_stack.return_address),
+            })
+            .collect()
+    }
+
+    /** Returns the name and the number of stack slots of the function declared at the address */
+    fn get_function_declaration(&self, address: u16) -> (Name, W) {
+        match self.instruction_cache.get(address as usize) {
+            Some(CpuInstruction::Fn(fn_name, size)) => (fn_name.clone(), *size),
+            _ => ("?".into(), W::ZERO),
+        }
+    }
+
+    /** Saves the current instruction as the return address in a new sub stack,
+    starting with the provided data, and enters the function declared at the address */
+    fn call_function(&mut self, address: u16, data: Vec<W>) -> Result<(), CpuError> {
+        let (fn_name, size) = self.get_function_declaration(address);
+        let depth = self.memory.get_depth();
+        if depth >= self.max_call_depth {
+            return Err(CpuError::StackOverflow { depth, fn_name });
+        }
+
+        self.memory
+            .create_new_sub_stack_with_data(self.instruction_pointer, data, size)?;
+        self.guard_current_frame();
+        self.instruction_pointer = address;
+        self.transferred = true;
+        Ok(())
+    }
+
+    /** The canary of a sub stack, derived from its return address so that changing either one is detected */
+    fn frame_canary(depth: usize, return_address: u16) -> u16 {
+        0xC0DE ^ return_address.rotate_left(7) ^ (depth as u16).wrapping_mul(0x9E37)
+    }
+
+    fn guard_current_frame(&mut self) {
+        if self.guard_frames {
+            let depth = self.memory.get_depth();
+            let sub_stack = self.memory.get_current_sub_stack_mut();
+            sub_stack.canary = Self::frame_canary(depth, sub_stack.return_address);
+        }
+    }
+
+    fn check_current_frame(&self) -> Result<(), CpuError> {
+        if !self.guard_frames {
+            return Ok(());
+        }
+
+        let depth = self.memory.get_depth();
+        let sub_stack = self.memory.get_current_sub_stack();
+        if sub_stack.canary != Self::frame_canary(depth, sub_stack.return_address) {
+            let fn_name = self
+                .get_function_at(self.instruction_pointer)
+                .unwrap_or("main".into());
+            return Err(CpuError::FrameCorrupted { depth, fn_name });
+        }
+        Ok(())
+    }
+
+    /** Enters the function between two instructions, so that returning from it
+    resumes at the current instruction instead of the following one */
+    fn interrupt_function(&mut self, address: u16) -> Result<(), CpuError> {
+        self.instruction_pointer = self.instruction_pointer.wrapping_sub(1);
+        self.call_function(address, vec![])
+    }
+
+    /** Calls a function of the program, or the host function registered under that name if the
+    program doesn't declare one. A host function runs immediately, without creating a sub stack */
+    fn call_named_function(&mut self, fn_name: &Name, data: Vec<W>) -> Result<(), CpuError> {
+        if !self.function_table.contains_key(fn_name)
+            && let Some(host_function) = self.host_functions.get_mut(fn_name)
+        {
+            let before = self.registers.clone();
+            let mut context = HostContext {
+                registers: &mut self.registers,
+                memory: &mut self.memory,
+                arguments: &data,
+            };
+            let value = host_function(&mut context);
+            self.notify_changed_registers(&before);
+            self.notify_memory_watchers();
+            if let Some(value) = value {
+                self.set_result(value);
+            }
+
+            return Ok(());
+        }
+
+        self.call_function(self.get_function_address(fn_name)?, data)
+    }
+
+    fn return_from_function(&mut self) -> Result<(), CpuError> {
+        self.check_current_frame()?;
+        let return_address = self.memory.get_current_sub_stack().return_address;
+        self.memory.rewind_stack();
+        self.instruction_pointer = return_address;
+        self.transferred = true;
+
+        // Leaving an interrupt handler restores the interrupts as they were before it
+        if let Some(&(depth, enabled)) = self.interrupt_frames.last()
+            && self.memory.get_depth() <= depth
+        {
+            self.interrupt_frames.pop();
+            self.interrupts_enabled = enabled;
+        }
+        Ok(())
+    }
+
+    /** Executes a block of instructions in sequence, until one of them transfers control or stops the CPU.
+    When one calls a function, pauses the CPU or fails, the rest of the block is kept to be resumed later,
+    so that a fault handler returns into the block */
+    fn handle_block(&mut self, instructions: Vec<CpuInstruction<W>>) -> Result<(), CpuError> {
+        let mut instructions = instructions.into_iter();
+        while let Some(instruction) = instructions.next() {
+            let depth = self.memory.get_depth();
+            let address = self.instruction_pointer;
+            let continuations = self.block_continuations.len();
+            let result = self.handle_instruction(instruction);
+
+            // The rest of an enclosing block comes after the rest of the nested block left
+            if self.block_continuations.len() > continuations {
+                let continuation = self.block_continuations.last_mut().unwrap();
+                continuation.instructions.extend(instructions);
+                return result;
+            }
+            if result.is_err()
+                || self.memory.get_depth() > depth
+                || matches!(self.status, CpuStatus::Paused)
+            {
+                let instructions: Vec<_> = instructions.collect();
+                if !instructions.is_empty() {
+                    self.block_continuations.push(BlockContinuation {
+                        depth,
+                        address,
+                        instructions,
+                    });
+                }
+                return result;
+            }
+            if self.transferred
+                || matches!(self.status, CpuStatus::Exiting | CpuStatus::Halted { .. })
+            {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_instruction(&mut self, instruction: CpuInstruction<W>) -> Result<(), CpuError> {
+        match instruction {
+            CpuInstruction::Add(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let (result, carry) = a.overflowing_add(b);
+                self.check_overflow(carry, a, b)?;
+                self.set_result(result);
+                self.update_arithmetic_flags(result, carry)
+            }
+            CpuInstruction::Sub(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let (result, borrow) = a.overflowing_sub(b);
+                self.check_overflow(borrow, a, b)?;
+                self.set_result(result);
+                self.update_arithmetic_flags(result, borrow)
+            }
+            CpuInstruction::Adc(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+                let carry_in = W::from_bool(self.carry_flag());
+
+                let (partial, first_carry) = a.overflowing_add(b);
+                let (result, second_carry) = partial.overflowing_add(carry_in);
+                self.set_result(result);
+                self.update_arithmetic_flags(result, first_carry || second_carry)
+            }
+            CpuInstruction::Sbb(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+                let borrow_in = W::from_bool(self.carry_flag());
+
+                let (partial, first_borrow) = a.overflowing_sub(b);
+                let (result, second_borrow) = partial.overflowing_sub(borrow_in);
+                self.set_result(result);
+                self.update_arithmetic_flags(result, first_borrow || second_borrow)
+            }
+            CpuInstruction::SatAdd(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let result = a.saturating_add(b);
+                self.set_result(result);
+                self.update_arithmetic_flags(result, a.checked_add(b).is_none())
+            }
+            CpuInstruction::SatSub(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let result = a.saturating_sub(b);
+                self.set_result(result);
+                self.update_arithmetic_flags(result, a < b)
+            }
+            CpuInstruction::Mul(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let (result, carry) = a.overflowing_mul(b);
+                self.check_overflow(carry, a, b)?;
+                self.set_result(result);
+                self.update_arithmetic_flags(result, carry)
+            }
+            CpuInstruction::MulWide(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let product = a.to_u64() * b.to_u64();
+                self.store_register(Register::D, W::from_u64(product >> W::BITS));
+                self.set_result(W::from_u64(product))
+            }
+            CpuInstruction::Add32() => {
+                let (first, second) = self.read_register_pairs();
+
+                let sum = first as u128 + second as u128;
+                self.write_register_pair(sum as u64, sum >> (2 * W::BITS) != 0)
+            }
+            CpuInstruction::Sub32() => {
+                let (first, second) = self.read_register_pairs();
+
+                let (result, borrow) = first.overflowing_sub(second);
+                self.write_register_pair(result, borrow)
+            }
+            CpuInstruction::Div(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let result = a.checked_div(b).ok_or(CpuError::DivisionByZero)?;
+                self.set_result(result);
+                self.update_arithmetic_flags(result, false)
+            }
+            CpuInstruction::Mod(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                let result = a.checked_rem(b).ok_or(CpuError::DivisionByZero)?;
+                self.set_result(result);
+                self.update_arithmetic_flags(result, false)
+            }
+            CpuInstruction::Neg(a) => {
+                let a = self.fetch_argument_value(a)?;
+
+                self.set_result(a.wrapping_neg())
+            }
+            CpuInstruction::Abs(a) => {
+                let a = self.fetch_argument_value(a)?.to_signed();
+
+                self.set_result(W::from_u64(a.unsigned_abs()))
+            }
+            CpuInstruction::And(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                self.set_result(a & b)
+            }
+            CpuInstruction::Or(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                self.set_result(a | b)
+            }
+            CpuInstruction::Xor(a, b) => {
+                let a = self.fetch_argument_value(a)?;
+                let b = self.fetch_argument_value(b)?;
+
+                self.set_result(a ^ b)
+            }
+            CpuInstruction::Not(a) => {
+                let a = self.fetch_argument_value(a)?;
+
+                self.set_result(!a)
+            }
+            CpuInstruction::Shl(value, amount) => {
+                let value = self.fetch_argument_value(value)?;
+                let amount = self.fetch_argument_value(amount)?;
+
+                self.set_result(
+                    u32::try_from(amount.to_u64())
+                        .ok()
+                        .and_then(|amount| value.checked_shl(amount))
+                        .unwrap_or(W::ZERO),
+                )
+            }
+            CpuInstruction::Shr(value, amount) => {
+                let value = self.fetch_argument_value(value)?;
+                let amount = self.fetch_argument_value(amount)?;
+
+                self.set_result(
+                    u32::try_from(amount.to_u64())
+                        .ok()
+                        .and_then(|amount| value.checked_shr(amount))
+                        .unwrap_or(W::ZERO),
+                )
+            }
+            CpuInstruction::Rol(value, amount) => {
+                let value = self.fetch_argument_value(value)?;
+                let amount = self.fetch_argument_value(amount)?;
+
+                self.set_result(value.rotate_left((amount.to_u64() % W::BITS as u64) as u32))
+            }
+            CpuInstruction::Ror(value, amount) => {
+                let value = self.fetch_argument_value(value)?;
+                let amount = self.fetch_argument_value(amount)?;
+
+                self.set_result(value.rotate_right((amount.to_u64() % W::BITS as u64) as u32))
+            }
+            CpuInstruction::Popcount(value) => {
+                let value = self.fetch_argument_value(value)?;
+
+                self.set_result(W::from_u64(value.count_ones().into()))
+            }
+            CpuInstruction::Clz(value) => {
+                let value = self.fetch_argument_value(value)?;
+
+                self.set_result(W::from_u64(value.leading_zeros().into()))
+            }
+            CpuInstruction::Ctz(value) => {
+                let value = self.fetch_argument_value(value)?;
+
+                self.set_result(W::from_u64(value.trailing_zeros().into()))
+            }
+            CpuInstruction::Sext8(value) => {
+                let value = self.fetch_argument_value(value)?;
+
+                self.set_result(W::from_u64(value.to_u64() as u8 as i8 as u64))
+            }
+            CpuInstruction::Zext8(value) => {
+                let value = self.fetch_argument_value(value)?;
+
+                self.set_result(W::from_u64(value.to_u64() & 0xFF))
+            }
+            CpuInstruction::SetBit(target, index) => {
+                let index = self.fetch_argument_value(index)?.to_u64() % W::BITS as u64;
+                let value = self.fetch_argument_value(target)? | W::from_u64(1 << index);
+                self.write_argument_value(target, value)?;
+
+                self.set_result(value)
+            }
+            CpuInstruction::ClearBit(target, index) => {
+                let index = self.fetch_argument_value(index)?.to_u64() % W::BITS as u64;
+                let value = self.fetch_argument_value(target)? & !W::from_u64(1 << index);
+                self.write_argument_value(target, value)?;
+
+                self.set_result(value)
+            }
+            CpuInstruction::TestBit(value, index) => {
+                let index = self.fetch_argument_value(index)?.to_u64() % W::BITS as u64;
+                let value = self.fetch_argument_value(value)?;
+
+                self.set_result(W::from_u64((value.to_u64() >> index) & 1))
+            }
+            CpuInstruction::Mov(
+                InstructionArgument::FloatRegister(from),
+                InstructionArgument::FloatRegister(to),
+            ) => *self.get_float_register_mut(to) = self.get_float_register(from),
+            CpuInstruction::Mov(from, to) => {
+                let from = self.fetch_argument_value(from)?;
+                self.write_argument_value(to, from)?;
+            }
+            CpuInstruction::Inc(target) => {
+                let current = self.fetch_argument_value(target)?;
+                let (value, carry) = current.overflowing_add(W::ONE);
+                self.check_overflow(carry, current, W::ONE)?;
+                self.write_argument_value(target, value)?;
+
+                self.set_result(value);
+                self.update_arithmetic_flags(value, carry)
+            }
+            CpuInstruction::Dec(target) => {
+                let current = self.fetch_argument_value(target)?;
+                let (value, borrow) = current.overflowing_sub(W::ONE);
+                self.check_overflow(borrow, current, W::ONE)?;
+                self.write_argument_value(target, value)?;
+
+                self.set_result(value);
+                self.update_arithmetic_flags(value, borrow)
+            }
+            CpuInstruction::Swap(first, second) => {
+                let first_value = self.fetch_argument_value(first)?;
+                let second_value = self.fetch_argument_value(second)?;
+
+                // Both addresses are computed before the first write, which may change a register
+                // the second one depends on
+                let first = self
+                    .resolve_location(first)
+                    .map_or(first, MemoryLocation::argument);
+                let second = self
+                    .resolve_location(second)
+                    .map_or(second, MemoryLocation::argument);
+
+                // Neither one is written unless both can be
+                self.check_destination(first)?;
+                self.check_destination(second)?;
+                self.write_argument_value(first, second_value)?;
+                self.write_argument_value(second, first_value)?;
+            }
+            CpuInstruction::Push(value) => {
+                let value = self.fetch_argument_value(value)?;
+                self.memory.push_value(value)?;
+                self.notify_memory_watchers();
+            }
+            CpuInstruction::Pop(target) => {
+                let value = self.memory.pop_value().ok_or(CpuError::StackUnderflow)?;
+                self.write_argument_value(target, value)?;
+            }
+            CpuInstruction::Reserve(length) => {
+                let length = self.fetch_argument_value(length)?;
+                self.memory.reserve_data(length)?;
+            }
+            CpuInstruction::MemCopy(destination, source, length) => {
+                let destination = self
+                    .resolve_location(destination)
+                    .ok_or(CpuError::NotAMemoryLocation)?;
+                let source = self
+                    .resolve_location(source)
+                    .ok_or(CpuError::NotAMemoryLocation)?;
+                let length = self.fetch_argument_value(length)?;
+                self.check_range(source, length)?;
+                self.check_writable_range(destination, length)?;
+
+                // The ranges were checked, so every offset location exists
+                let offsets = || (0..length.to_u64()).map(W::from_u64);
+                let values = offsets()
+                    .map(|offset| self.read_location(source.offset(offset).unwrap()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for (offset, value) in offsets().zip(values) {
+                    self.write_location(destination.offset(offset).unwrap(), value)?;
+                }
+            }
+            CpuInstruction::MemSet(destination, value, length) => {
+                let destination = self
+                    .resolve_location(destination)
+                    .ok_or(CpuError::NotAMemoryLocation)?;
+                let value = self.fetch_argument_value(value)?;
+                let length = self.fetch_argument_value(length)?;
+                self.check_writable_range(destination, length)?;
+
+                for offset in (0..length.to_u64()).map(W::from_u64) {
+                    self.write_location(destination.offset(offset).unwrap(), value)?;
+                }
+            }
+            CpuInstruction::Alloc(size) => {
+                let size = self.fetch_argument_value(size)?;
+                let address = self.memory.allocate(size)?;
+                self.notify_memory_watchers();
+                self.set_result(address);
+            }
+            CpuInstruction::Free(address) => {
+                let address = self.fetch_argument_value(address)?;
+                self.memory.free(address)?;
+            }
+            CpuInstruction::Eq(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(W::from_bool(first == second))
+            }
+            CpuInstruction::Neq(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(W::from_bool(first != second))
+            }
+            CpuInstruction::Lt(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(W::from_bool(first < second))
+            }
+            CpuInstruction::Gt(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(W::from_bool(first > second))
+            }
+            CpuInstruction::Le(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(W::from_bool(first <= second))
+            }
+            CpuInstruction::Ge(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(W::from_bool(first >= second))
+            }
+            CpuInstruction::Slt(first, second) => {
+                let first = self.fetch_argument_value(first)?.to_signed();
+                let second = self.fetch_argument_value(second)?.to_signed();
+
+                self.set_result(W::from_bool(first < second))
+            }
+            CpuInstruction::Sgt(first, second) => {
+                let first = self.fetch_argument_value(first)?.to_signed();
+                let second = self.fetch_argument_value(second)?.to_signed();
+
+                self.set_result(W::from_bool(first > second))
+            }
+            CpuInstruction::Sle(first, second) => {
+                let first = self.fetch_argument_value(first)?.to_signed();
+                let second = self.fetch_argument_value(second)?.to_signed();
+
+                self.set_result(W::from_bool(first <= second))
+            }
+            CpuInstruction::Sge(first, second) => {
+                let first = self.fetch_argument_value(first)?.to_signed();
+                let second = self.fetch_argument_value(second)?.to_signed();
+
+                self.set_result(W::from_bool(first >= second))
+            }
+            CpuInstruction::Min(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(first.min(second))
+            }
+            CpuInstruction::Max(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_result(first.max(second))
+            }
+            CpuInstruction::Cmp(first, second) => {
+                let first = self.fetch_argument_value(first)?;
+                let second = self.fetch_argument_value(second)?;
+
+                self.set_flags(
+                    first == second,
+                    first.to_signed() < second.to_signed(),
+                    first < second,
+                )
+            }
+            CpuInstruction::FAdd(a, b) => {
+                self.registers.fres = self.fetch_float_value(a)? + self.fetch_float_value(b)?
+            }
+            CpuInstruction::FSub(a, b) => {
+                self.registers.fres = self.fetch_float_value(a)? - self.fetch_float_value(b)?
+            }
+            CpuInstruction::FMul(a, b) => {
+                self.registers.fres = self.fetch_float_value(a)? * self.fetch_float_value(b)?
+            }
+            CpuInstruction::FDiv(a, b) => {
+                self.registers.fres = self.fetch_float_value(a)? / self.fetch_float_value(b)?
+            }
+            CpuInstruction::IntToFloat(value) => {
+                self.registers.fres = self.fetch_argument_value(value)?.to_u64() as f32
+            }
+            CpuInstruction::FloatToInt(value) => {
+                let value = self.fetch_float_value(value)?.trunc();
+                let in_range = (0.0..=W::MAX.to_u64() as f64).contains(&(value as f64));
+
+                // Casting a float saturates, and gives 0 for NaN
+                let result = W::from_u64((value as u64).min(W::MAX.to_u64()));
+                self.set_result(result);
+                self.update_arithmetic_flags(result, !in_range)
+            }
+            CpuInstruction::Fn(..) => {}
+            CpuInstruction::Ret() => self.return_from_function()?,
+            CpuInstruction::RetVal(value) => {
+                let value = self.fetch_argument_value(value)?;
+                // Set while still at the RETVAL, so that watchers see its address
+                self.set_result(value);
+
+                self.return_from_function()?;
+            }
+            CpuInstruction::Call(fn_name) => self.call_named_function(&fn_name, vec![])?,
+            CpuInstruction::CallIf(condition, fn_name) => {
+                if self.fetch_argument_value(condition)? >= W::ONE {
+                    self.call_named_function(&fn_name, vec![])?;
+                }
+            }
+            CpuInstruction::CallArgs(fn_name, arguments) => {
+                let arguments = arguments
+                    .into_iter()
+                    .map(|argument| self.fetch_argument_value(argument))
+                    .collect::<Result<_, _>>()?;
+                self.call_named_function(&fn_name, arguments)?;
+            }
+            CpuInstruction::CallIndirect(address) => {
+                let address = self.fetch_argument_value(address)?;
+                if !matches!(
+                    self.instruction_cache.get(address.to_usize()),
+                    Some(CpuInstruction::Fn(..))
+                ) {
+                    return Err(CpuError::NotAFunction(address.to_u64()));
+                }
+
+                self.call_function(address.to_u64() as u16, vec![])?;
+            }
+            CpuInstruction::TailCall(fn_name) => {
+                let return_address = self.memory.get_current_sub_stack().return_address;
+                let address = self.get_function_address(&fn_name)?;
+                self.check_current_frame()?;
+                self.memory.rewind_stack();
+                self.memory.create_new_sub_stack_with_data(
+                    return_address,
+                    vec![],
+                    self.get_function_declaration(address).1,
+                )?;
+                self.guard_current_frame();
+                self.instruction_pointer = address;
+                self.transferred = true;
+            }
+            CpuInstruction::Int(number) => {
+                let number = self.fetch_argument_value(number)?;
+                let address = self.get_interrupt_handler(number)?;
+                self.enter_interrupt_handler();
+                self.call_function(address, vec![])?;
+            }
+            CpuInstruction::Cli() => self.interrupts_enabled = false,
+            CpuInstruction::Sti() => self.interrupts_enabled = true,
+            CpuInstruction::LoadFnAddr(fn_name) => {
+                self.set_result(W::from_u64(self.get_function_address(&fn_name)?.into()))
+            }
+            CpuInstruction::Goto(new_address) => {
+                self.jump_to(new_address as i32)?;
+            }
+            CpuInstruction::GotoIf(condition, address) => {
+                if self.fetch_argument_value(condition)? >= W::ONE {
+                    self.jump_to(address as i32)?;
+                }
+            }
+            CpuInstruction::Loop(counter, label_name) => {
+                let value = self.fetch_argument_value(counter)?.wrapping_sub(W::ONE);
+                self.write_argument_value(counter, value)?;
+
+                if value != W::ZERO {
+                    self.jump_to(self.get_label_address(&label_name)? as i32)?;
+                }
+            }
+            CpuInstruction::Switch(selector, table) => {
+                let selector = self.fetch_argument_value(selector)?;
+
+                if let Some(&address) = table.get(selector.to_usize()) {
+                    self.jump_to(address as i32)?;
+                }
+            }
+            CpuInstruction::Label(_) => {}
+            CpuInstruction::Jump(offset) => {
+                self.jump_to(self.instruction_pointer as i32 + offset as i32)?;
+            }
+            CpuInstruction::Jz(condition, label_name) => {
+                if self.fetch_argument_value(condition)? == W::ZERO {
+                    self.jump_to(self.get_label_address(&label_name)? as i32)?;
+                }
+            }
+            CpuInstruction::Jnz(condition, label_name) => {
+                if self.fetch_argument_value(condition)? != W::ZERO {
+                    self.jump_to(self.get_label_address(&label_name)? as i32)?;
+                }
+            }
+            CpuInstruction::GotoLabel(label_name) => {
+                self.jump_to(self.get_label_address(&label_name)? as i32)?;
+            }
+            CpuInstruction::If(boolean, first, second) => {
+                let boolean = self.fetch_argument_value(boolean)?;
+
+                if boolean >= W::ONE {
+                    self.handle_instruction(*first)?;
+                } else {
+                    self.handle_instruction(*second)?;
+                }
+            }
+            CpuInstruction::IfThen(boolean, instruction) => {
+                let boolean = self.fetch_argument_value(boolean)?;
+
+                if boolean >= W::ONE {
+                    self.handle_instruction(*instruction)?;
+                }
+            }
+            CpuInstruction::IfBlock(boolean, first, second) => {
+                let boolean = self.fetch_argument_value(boolean)?;
+
+                if boolean >= W::ONE {
+                    self.handle_block(first)?;
+                } else {
+                    self.handle_block(second)?;
+                }
+            }
+            CpuInstruction::Wait(cycles) => {
+                self.instruction_cycles = self.fetch_argument_value(cycles)?.to_u64().max(1);
+            }
+            CpuInstruction::Nop() => {}
+            CpuInstruction::Rand(bound) => {
+                let bound = self.fetch_argument_value(bound)?;
+
+                let value = self.rng.next_bounded(bound.to_u64(), W::BITS);
+                self.set_result(W::from_u64(value))
+            }
+            CpuInstruction::Print(value) => {
+                let value = self.fetch_argument_value(value)?;
+                writeln!(self.output, "{value}")?;
+            }
+            CpuInstruction::PrintChar(value) => {
+                let value = self.fetch_argument_value(value)?;
+                self.output.write_all(&[value.to_u64() as u8])?;
+            }
+            CpuInstruction::PrintStr(start, length) => {
+                let start = match self.resolve_location(start) {
+                    Some(location) => location,
+                    None => MemoryLocation::Stack(self.fetch_argument_value(start)?),
+                };
+                let length = self.fetch_argument_value(length)?;
+                self.check_range(start, length)?;
+
+                let bytes = (0..length.to_u64())
+                    .map(|offset| {
+                        let offset = W::from_u64(offset);
+                        let value = self.read_location(start.offset(offset).unwrap())?;
+                        Ok(value.to_u64() as u8)
+                    })
+                    .collect::<Result<Vec<_>, CpuError>>()?;
+                self.output.write_all(&bytes)?;
+            }
+            CpuInstruction::Read(target) => {
+                let value = self
+                    .input
+                    .read_value()
+                    .and_then(W::try_from_u64)
+                    .ok_or(CpuError::NoInput)?;
+                self.write_argument_value(target, value)?;
+            }
+            CpuInstruction::Syscall(number) => {
+                let number = self.fetch_argument_value(number)?;
+                let handler = self
+                    .syscalls
+                    .get_mut(&number)
+                    .ok_or(CpuError::UnknownSyscall(number.to_u64()))?;
+
+                let before = self.registers.clone();
+                handler(&mut self.registers, &mut self.memory);
+                self.notify_changed_registers(&before);
+                self.notify_memory_watchers();
+            }
+            CpuInstruction::Custom(code, arguments) => {
+                let arguments = arguments
+                    .into_iter()
+                    .map(|argument| self.fetch_argument_value(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let handler = self
+                    .custom_ops
+                    .get_mut(&code)
+                    .ok_or(CpuError::IllegalInstruction(code))?;
+
+                let before = self.registers.clone();
+                let value = handler(
+                    &arguments,
+                    &mut self.registers,
+                    self.memory.get_current_sub_stack_mut(),
+                );
+                self.notify_changed_registers(&before);
+                self.set_result(value);
+            }
+            CpuInstruction::Brk() => self.status = CpuStatus::Paused,
+            CpuInstruction::Assert(actual, expected) => {
+                let actual = self.fetch_argument_value(actual)?;
+                let expected = self.fetch_argument_value(expected)?;
+
+                if actual != expected {
+                    return Err(CpuError::AssertionFailed {
+                        actual: actual.to_u64(),
+                        expected: expected.to_u64(),
+                    });
+                }
+            }
+            CpuInstruction::Halt(code) => {
+                let code = self.fetch_argument_value(code)?;
+                self.status = CpuStatus::Halted {
+                    code,
+                    address: self.instruction_pointer,
+                };
+            }
+            CpuInstruction::Exit() => self.status = CpuStatus::Exiting,
+        }
+
+        Ok(())
+    }
+
+    /** Runs the program from its "main" function until it stops, see `ExecutionReport`.
+    It can only run once, a CPU paused by a BRK instruction continues with `resume` */
+    pub fn execute(&mut self) -> Result<ExecutionReport<W>, CpuError> {
+        if let Err(error) = self.start() {
+            // A CPU that already started keeps the status it has
+            if let CpuStatus::NotStarted = self.status {
+                self.status = CpuStatus::Failed(error.clone());
+            }
+            return Err(error);
+        }
+        self.run()
+    }
+
+    /** Prepares the CPU to execute the program from its "main" function, without executing anything yet.
+    `execute` does it before running the program, `step` can then execute it one instruction at a time.
+    A CPU that already started can't be started again */
+    pub fn start(&mut self) -> Result<(), CpuError> {
+        if !matches!(self.status, CpuStatus::NotStarted) {
+            return Err(CpuError::AlreadyStarted);
+        }
+        if !self.function_table.contains_key("main") {
+            return Err(CpuError::UnknownFunction("main".into()));
+        }
+
+        let arguments = self
+            .main_frame_data
+            .iter()
+            .map(|&value| InstructionArgument::Value(value))
+            .collect();
+        self.instruction_cache
+            .push(CpuInstruction::CallArgs("main".into(), arguments));
+        self.instruction_pointer = (self.instruction_cache.len() - 1) as u16;
+        self.entry_address = Some(self.instruction_pointer);
+
+        self.status = CpuStatus::Running;
+        Ok(())
+    }
+
+    /** Copies the state of the CPU, see `CpuSnapshot` */
+    pub fn snapshot(&self) -> CpuSnapshot<W> {
+        CpuSnapshot {
+            registers: self.registers.clone(),
+            instruction_pointer: self.instruction_pointer,
+            status: self.status.clone(),
+            memory: self.memory.clone(),
+            cycles: self.cycles,
+            instructions: self.instructions,
+            interrupts_enabled: self.interrupts_enabled,
+            timer_pending: self.timer_pending,
+            interrupt_frames: self.interrupt_frames.clone(),
+            block_continuations: self.block_continuations.clone(),
+            timer_next_fire: self.timer.as_ref().map(|timer| timer.next_fire),
+            rng: self.rng.clone(),
+        }
+    }
+
+    /** Puts back the state of the CPU at the time of the snapshot */
+    pub fn restore(&mut self, snapshot: &CpuSnapshot<W>) {
+        self.registers = snapshot.registers.clone();
+        self.instruction_pointer = snapshot.instruction_pointer;
+        self.status = snapshot.status.clone();
+        // The watchers stay registered, so the slots they watch are still recorded
+        let watched_slots = self.memory.watched_slots().to_vec();
+        self.memory = snapshot.memory.clone();
+        for slot in watched_slots {
+            self.memory.watch(slot);
+        }
+        self.cycles = snapshot.cycles;
+        self.instructions = snapshot.instructions;
+        self.interrupts_enabled = snapshot.interrupts_enabled;
+        self.timer_pending = snapshot.timer_pending;
+        self.interrupt_frames = snapshot.interrupt_frames.clone();
+        self.block_continuations = snapshot.block_continuations.clone();
+        if let (Some(timer), Some(next_fire)) = (&mut self.timer, snapshot.timer_next_fire) {
+            timer.next_fire = next_fire;
+        }
+        self.rng = snapshot.rng.clone();
+    }
+
+    /** Continues the execution of a CPU paused by a BRK instruction,
+    a CPU that isn't paused reports how it stopped without executing anything */
+    pub fn resume(&mut self) -> Result<ExecutionReport<W>, CpuError> {
+        if let CpuStatus::Paused = self.status {
+            self.status = CpuStatus::Running;
+        }
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<ExecutionReport<W>, CpuError> {
+        let start = std::time::Instant::now();
+        while self.step() {}
+
+        let exit = match &self.status {
+            CpuStatus::Failed(error) => return Err(error.clone()),
+            CpuStatus::Paused => ExitReason::Paused {
+                address: self.instruction_pointer,
+            },
+            &CpuStatus::Halted { code, address } => ExitReason::Halted { code, address },
+            CpuStatus::Exiting => ExitReason::Exited,
+            CpuStatus::NotStarted | CpuStatus::Running => ExitReason::Finished,
+        };
+        let report = ExecutionReport {
+            exit,
+            instructions: self.instructions,
+            cycles: self.cycles,
+            duration: start.elapsed(),
+            registers: self.registers.clone(),
+        };
+        if self.verbose {
+            self.report(&report.to_string());
+        }
+
+        Ok(report)
+    }
+
+    /** Executes the next instruction. Returns false, without executing anything, once the CPU isn't running
+    anymore or ran out of instructions, and when the instruction made it halt or fail */
+    pub fn step(&mut self) -> bool {
+        if !matches!(self.status, CpuStatus::Running)
+            || (self.instruction_pointer as usize >= self.instruction_cache.len()
+                && !self.block_continuation_ready())
+        {
+            return false;
+        }
+
+        let instruction_start = std::time::Instant::now();
+        // A timer interrupt is entered before the next instruction of the program
+        if let Err(error) = self.poll_timer() {
+            self.fail(error);
+            return false;
+        }
+        // Simulate one CPU instruction
+        self.transferred = false;
+        let result = if self.block_continuation_ready() {
+            // Back at the depth of an interrupted block, the rest of it runs in place of the next instruction
+            let continuation = self.block_continuations.pop().unwrap();
+            self.instruction_pointer = continuation.address;
+            self.handle_block(continuation.instructions)
+        } else {
+            let current_instruction =
+                self.instruction_cache[self.instruction_pointer as usize].clone();
+            self.handle_instruction(current_instruction)
+        };
+        if let Err(error) = result.or_else(|error| self.handle_fault(error)) {
+            // Nothing resumes a failed CPU
+            self.block_continuations.clear();
+            self.fail(error);
+            return false;
+        }
+        let cycles = std::mem::replace(&mut self.instruction_cycles, 1);
+        self.cycles += cycles;
+        self.instructions += 1;
+
+        if let CpuStatus::Halted { .. } = self.status {
+            return false;
+        }
+        // println!(
+        //     "{:#?}",
+        //     self.instruction_cache[self.instruction_pointer as usize]
+        // );
+        if self.trace {
+            self.report(&format!(
+                "{}: {}",
+                self.instruction_pointer, self.registers.res
+            ));
+        }
+
+        // Increment the instruction address, unless the instruction jumped to its target
+        if !std::mem::take(&mut self.jumped) {
+            self.instruction_pointer = self.instruction_pointer.wrapping_add(1);
+        }
+
+        // Sleep to maintain CPU frequency
+        let elapsed = instruction_start.elapsed().as_millis_f64();
+        let expected = self.cycle_duration * cycles as f64;
+        if elapsed < expected {
+            let sleep_duration = expected - elapsed;
+            std::thread::sleep(std::time::Duration::from_millis(sleep_duration as u64));
+        }
+
+        true
+    }
+
+    /** Whether the innermost interrupted block is at the current depth, and runs next */
+    fn block_continuation_ready(&self) -> bool {
+        self.block_continuations
+            .last()
+            .is_some_and(|continuation| continuation.depth == self.memory.get_depth())
+    }
+
+    /** Reports the error and stops the CPU with it, along with a backtrace of the current instruction */
+    fn fail(&mut self, error: CpuError) {
+        let address = self.instruction_pointer;
+        let backtrace = Backtrace {
+            address,
+            instruction: self
+                .instruction_cache
+                .get(address as usize)
+                .map(|instruction| format!("{instruction:?}"))
+                .unwrap_or_default(),
+            function: self.get_function_at(address),
+            frames: self.call_stack(),
+        };
+        let error = CpuError::Backtraced {
+            error: Box::new(error),
+            backtrace,
+        };
+
+        if self.verbose {
+            self.report(&format!(
+                "CPU error at instruction {}: {error}",
+                self.instruction_pointer
+            ));
+        }
+        self.status = CpuStatus::Failed(error);
+    }
+
+    /** Writes the content of the memory to the writer, see `MemoryState::dump` */
+    pub fn dump_memory(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        self.memory.dump(writer)
+    }
+
+    /** Writes a message about the CPU itself, rather than from the program, to the output */
+    fn report(&mut self, message: &str) {
+        // There is nowhere left to report a failing output to, so the message is dropped
+        let _ = writeln!(self.output, "{message}");
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender};
+
+/** A virtual device that programs reach through a range of stack addresses */
+pub trait Device {
+    /** Returns the value at the offset from the start of the mapped range */
+    fn read(&mut self, offset: u16) -> u16;
+    /** Receives a value written at the offset from the start of the mapped range */
+    fn write(&mut self, offset: u16, value: u16);
+}
+
+/** Returns one more every time it is read, starting at 0, whatever the offset. Writing sets the count */
+#[derive(Debug, Default)]
+pub struct CounterDevice {
+    count: u16,
+}
+impl Device for CounterDevice {
+    fn read(&mut self, _offset: u16) -> u16 {
+        let count = self.count;
+        self.count = self.count.wrapping_add(1);
+
+        count
+    }
+
+    fn write(&mut self, _offset: u16, value: u16) {
+        self.count = value;
+    }
+}
+
+/** A serial console exchanging bytes with the host through channels, it needs 3 addresses */
+#[derive(Debug)]
+pub struct SerialDevice {
+    output: Sender<u8>,
+    input: Receiver<u8>,
+    /** Set when the last read of the input found no data */
+    no_data: bool,
+}
+impl SerialDevice {
+    /** Writing the low 8 bits of a value here sends them to the host */
+    pub const OUTPUT: u16 = 0;
+    /** Reading here returns the next byte sent by the host, or 0 when there is none */
+    pub const INPUT: u16 = 1;
+    /** Reading here returns 1 when the last read of the input found no data, 0 otherwise */
+    pub const STATUS: u16 = 2;
+
+    pub fn new(output: Sender<u8>, input: Receiver<u8>) -> SerialDevice {
+        SerialDevice {
+            output,
+            input,
+            no_data: false,
+        }
+    }
+}
+impl Device for SerialDevice {
+    fn read(&mut self, offset: u16) -> u16 {
+        match offset {
+            SerialDevice::INPUT => match self.input.try_recv() {
+                Ok(byte) => {
+                    self.no_data = false;
+                    byte as u16
+                }
+                Err(_) => {
+                    self.no_data = true;
+                    0
+                }
+            },
+            SerialDevice::STATUS => self.no_data as u16,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        if offset == SerialDevice::OUTPUT {
+            // Nobody is listening anymore once the host dropped its receiver, the byte is lost
+            let _ = self.output.send(value as u8);
+        }
+    }
+}
+
+/** A text screen of 80 columns and 25 rows, row after row, every address holding a character code.
+Clones share the same screen, so the host can keep one to render what the program wrote to the mapped one */
+#[derive(Clone, Debug)]
+pub struct FrameBufferDevice {
+    cells: Rc<RefCell<Vec<u16>>>,
+}
+impl FrameBufferDevice {
+    pub const WIDTH: u16 = 80;
+    pub const HEIGHT: u16 = 25;
+
+    pub fn new() -> FrameBufferDevice {
+        FrameBufferDevice {
+            cells: Rc::new(RefCell::new(vec![
+                0;
+                (FrameBufferDevice::WIDTH * FrameBufferDevice::HEIGHT)
+                    as usize
+            ])),
+        }
+    }
+
+    /** Returns the rows of the screen without their trailing spaces, separated by new lines.
+    The code 0 shows as a space and codes that aren't characters as '?' */
+    pub fn render(&self) -> String {
+        self.cells
+            .borrow()
+            .chunks(FrameBufferDevice::WIDTH as usize)
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .map(|&code| match code {
+                        0 => ' ',
+                        _ => char::from_u32(code as u32).unwrap_or('?'),
+                    })
+                    .collect();
+                line.trim_end().to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+impl Default for FrameBufferDevice {
+    fn default() -> Self {
+        FrameBufferDevice::new()
+    }
+}
+impl Device for FrameBufferDevice {
+    fn read(&mut self, offset: u16) -> u16 {
+        self.cells
+            .borrow()
+            .get(offset as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /** Writes past the last cell of the screen are ignored */
+    fn write(&mut self, offset: u16, value: u16) {
+        if let Some(cell) = self.cells.borrow_mut().get_mut(offset as usize) {
+            *cell = value;
+        }
+    }
+}
+
+/** A device along with the range of addresses it is mapped to */
+pub struct MappedDevice {
+    pub start: u16,
+    pub length: u16,
+    pub device: Box<dyn Device>,
+}
+/** The device itself can't be printed, only its range is */
+impl std::fmt::Debug for MappedDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MappedDevice")
+            .field("start", &self.start)
+            .field("length", &self.length)
+            .finish_non_exhaustive()
+    }
+}
+impl MappedDevice {
+    fn end(&self) -> u32 {
+        self.start as u32 + self.length as u32
+    }
+
+    /** Returns the offset of the address in the range, if it is inside */
+    pub fn offset_of(&self, address: u16) -> Option<u16> {
+        (address >= self.start && (address as u32) < self.end()).then(|| address - self.start)
+    }
+
+    pub fn overlaps(&self, start: u16, length: u16) -> bool {
+        (start as u32) < self.end() && (self.start as u32) < start as u32 + length as u32
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::VecDeque;
+
+/** Where the READ instruction gets its values from */
+pub trait InputSource {
+    /** Returns the next value, or None when the input ended or isn't a valid number.
+    The CPU checks that the value fits in its word */
+    fn read_value(&mut self) -> Option<u64>;
+}
+
+/** Reads one value per line from the standard input */
+#[derive(Debug)]
+pub struct StdinInput;
+impl InputSource for StdinInput {
+    fn read_value(&mut self) -> Option<u64> {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => line.trim().parse().ok(),
+        }
+    }
+}
+
+/** Hands out predetermined values in order */
+impl InputSource for VecDeque<u64> {
+    fn read_value(&mut self) -> Option<u64> {
+        self.pop_front()
+    }
+}
@@ -0,0 +1,11 @@
+#![feature(duration_millis_float)]
+/*! An emulated CPU running programs built from `cpu::CpuInstruction`s, with a configurable word size,
+host functions and handlers, memory-mapped devices and interrupts */
+
+pub mod cpu;
+pub mod device;
+pub mod input;
+pub mod memory;
+pub mod names;
+mod random;
+pub mod word;
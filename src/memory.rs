@@ -1,43 +1,684 @@
-#[derive(Default)]
-pub struct SubStack {
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::word::Word;
+
+/** Errors of the memory accesses, they stop the CPU like the other errors */
+#[derive(Clone, Debug)]
+pub enum MemoryError {
+    /** The address is outside of a sub stack with a declared size */
+    OutOfBounds { address: u64, size: u64 },
+    /** The caller's sub stack was accessed from a function without a caller */
+    NoCallerFrame,
+    /** There is no free space left in the heap for a block of that size */
+    HeapExhausted { size: u64 },
+    /** The address isn't the start of an allocated heap block, or it was already freed */
+    InvalidFree(u64),
+    /** Growing a sub stack up to the address would make it larger than the maximum frame size */
+    FrameLimitExceeded { address: u64, limit: usize },
+    /** Growing the memory up to the address would go beyond the maximum total memory */
+    MemoryLimitExceeded { address: u64, limit: usize },
+}
+impl std::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryError::OutOfBounds { address, size } => write!(
+                f,
+                "Address {address} is out of bounds, the sub stack only has {size} slots"
+            ),
+            MemoryError::NoCallerFrame => write!(
+                f,
+                "Cannot access the caller's sub stack, there is no caller"
+            ),
+            MemoryError::HeapExhausted { size } => {
+                write!(f, "Cannot allocate {size} slots, the heap is full")
+            }
+            MemoryError::InvalidFree(address) => write!(
+                f,
+                "Cannot free address {address}, it is not the start of an allocated block"
+            ),
+            MemoryError::FrameLimitExceeded { address, limit } => write!(
+                f,
+                "Address {address} is out of bounds, a sub stack cannot hold more than {limit} slots"
+            ),
+            MemoryError::MemoryLimitExceeded { address, limit } => write!(
+                f,
+                "Address {address} is out of bounds, the memory cannot hold more than {limit} slots in total"
+            ),
+        }
+    }
+}
+
+/** How the slots of the sub stacks and of the global memory are stored */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MemoryBacking {
+    /** Every slot up to the highest one written is stored, the fastest for small contiguous memories */
+    #[default]
+    Dense,
+    /** Only the slots that were written are stored, for memories using a few scattered addresses */
+    Sparse,
+}
+
+/** The slots of a sub stack or of the global memory, the slots that were never written read as 0 */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Slots<W: Word = u16> {
+    Dense(Vec<W>),
+    Sparse {
+        values: HashMap<W, W>,
+        /** The number of slots the memory holds, one more than the highest slot written or reserved */
+        length: usize,
+    },
+}
+impl<W: Word> Slots<W> {
+    pub fn new(backing: MemoryBacking) -> Slots<W> {
+        match backing {
+            MemoryBacking::Dense => Slots::Dense(vec![]),
+            MemoryBacking::Sparse => Slots::Sparse {
+                values: HashMap::new(),
+                length: 0,
+            },
+        }
+    }
+
+    fn from_vec(backing: MemoryBacking, data: Vec<W>) -> Slots<W> {
+        match backing {
+            MemoryBacking::Dense => Slots::Dense(data),
+            MemoryBacking::Sparse => Slots::Sparse {
+                length: data.len(),
+                values: (0..).map(W::from_usize).zip(data).collect(),
+            },
+        }
+    }
+
+    /** The number of slots the memory holds, PUSH adds a value after the last one */
+    pub fn len(&self) -> usize {
+        match self {
+            Slots::Dense(data) => data.len(),
+            Slots::Sparse { length, .. } => *length,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /** The number of slots actually stored, which is what the memory limits count */
+    pub fn stored_len(&self) -> usize {
+        match self {
+            Slots::Dense(data) => data.len(),
+            Slots::Sparse { values, .. } => values.len(),
+        }
+    }
+
+    /** The number of slots that writing the address would add to the stored ones */
+    fn growth(&self, address: W) -> usize {
+        match self {
+            Slots::Dense(data) => (address.to_usize() + 1).saturating_sub(data.len()),
+            Slots::Sparse { values, .. } => !values.contains_key(&address) as usize,
+        }
+    }
+
+    /** The number of slots that writing `length` slots from the address would add to the stored ones */
+    fn range_growth(&self, address: W, length: W) -> usize {
+        match self {
+            Slots::Dense(data) => {
+                (address.to_usize() + length.to_usize()).saturating_sub(data.len())
+            }
+            Slots::Sparse { values, .. } => (0..length.to_u64())
+                .filter(|&offset| !values.contains_key(&address.wrapping_add(W::from_u64(offset))))
+                .count(),
+        }
+    }
+
+    /** The number of slots that reserving `length` slots would add to the stored ones */
+    fn growth_to_length(&self, length: usize) -> usize {
+        match self {
+            Slots::Dense(data) => length.saturating_sub(data.len()),
+            Slots::Sparse { .. } => 0,
+        }
+    }
+
+    pub fn get(&self, address: W) -> W {
+        match self {
+            Slots::Dense(data) => data.get(address.to_usize()).copied().unwrap_or(W::ZERO),
+            Slots::Sparse { values, .. } => values.get(&address).copied().unwrap_or(W::ZERO),
+        }
+    }
+
+    pub fn set(&mut self, address: W, value: W) {
+        match self {
+            Slots::Dense(data) => {
+                if data.get(address.to_usize()).is_none() {
+                    data.resize(address.to_usize() + 1, W::ZERO);
+                }
+                data[address.to_usize()] = value;
+            }
+            Slots::Sparse { values, length } => {
+                values.insert(address, value);
+                *length = (*length).max(address.to_usize() + 1);
+            }
+        }
+    }
+
+    /** Makes the memory hold at least `length` slots, the new ones being 0 */
+    pub fn reserve(&mut self, length: usize) {
+        match self {
+            Slots::Dense(data) => {
+                if length > data.len() {
+                    data.resize(length, W::ZERO);
+                }
+            }
+            Slots::Sparse {
+                length: current_length,
+                ..
+            } => *current_length = (*current_length).max(length),
+        }
+    }
+
+    pub fn push(&mut self, value: W) {
+        match self {
+            Slots::Dense(data) => data.push(value),
+            Slots::Sparse { values, length } => {
+                values.insert(W::from_usize(*length), value);
+                *length += 1;
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<W> {
+        match self {
+            Slots::Dense(data) => data.pop(),
+            Slots::Sparse { values, length } => {
+                *length = length.checked_sub(1)?;
+                Some(values.remove(&W::from_usize(*length)).unwrap_or(W::ZERO))
+            }
+        }
+    }
+
+    /** Every slot the memory holds, in order */
+    pub fn to_vec(&self) -> Vec<W> {
+        (0..self.len())
+            .map(|address| self.get(W::from_usize(address)))
+            .collect()
+    }
+}
+
+/** The return address is the address of an instruction, which stays 16-bit whatever the word size */
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubStack<W: Word = u16> {
     pub return_address: u16,
-    pub data: Vec<u16>,
+    /** The number of slots declared by the function, sub stacks without one grow on demand */
+    pub size: Option<W>,
+    pub data: Slots<W>,
+    /** Recorded when the sub stack is created with frame guards enabled, and checked when returning from it */
+    pub canary: u16,
 }
+impl<W: Word> SubStack<W> {
+    fn check_bounds(&self, address: W) -> Result<(), MemoryError> {
+        match self.size {
+            Some(size) if address >= size => Err(MemoryError::OutOfBounds {
+                address: address.to_u64(),
+                size: size.to_u64(),
+            }),
+            _ => Ok(()),
+        }
+    }
 
-#[derive(Default)]
-pub struct MemoryState {
-    stack: Vec<SubStack>,
+    /** Slots that were never written read as 0, without growing the sub stack */
+    fn read_data(&self, address: W) -> Result<W, MemoryError> {
+        self.check_bounds(address)?;
+
+        Ok(self.data.get(address))
+    }
+
+    fn write_data(&mut self, address: W, data: W) -> Result<(), MemoryError> {
+        self.check_bounds(address)?;
+        self.data.set(address, data);
+
+        Ok(())
+    }
 }
-impl MemoryState {
-    pub fn create_new_sub_stack(&mut self, return_address: u16) {
+
+/** A slot of the memory whose writes are recorded, see `MemoryState::watch` */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchedSlot<W: Word = u16> {
+    /** The stack address, in whichever sub stack it is written */
+    Stack(W),
+    Global(W),
+}
+
+/** A write to a watched slot, with the value it replaced */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlotWrite<W: Word = u16> {
+    pub slot: WatchedSlot<W>,
+    pub old_value: W,
+    pub new_value: W,
+}
+
+/** The first global address used by the heap, the middle of the address space for 8-bit
+and 16-bit words, and 0x8000 for wider words so that the dense backing can reach it.
+The global addresses below it are never allocated */
+pub fn heap_start<W: Word>() -> W {
+    W::from_u64((W::MAX.to_u64() / 2 + 1).min(0x8000))
+}
+
+/** The sub stacks of the function calls in progress, and the global memory holding the heap */
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryState<W: Word = u16> {
+    /** How the slots of every memory are stored */
+    backing: MemoryBacking,
+    /** The sub stack of the first function first and the current one last,
+    so that calling and returning push and pop without moving the other sub stacks */
+    stack: Vec<SubStack<W>>,
+    /** Shared by every function and kept across calls, it grows on demand like a sub stack without a size */
+    global: Slots<W>,
+    /** The start and size of every allocated heap block */
+    heap_blocks: HashMap<W, W>,
+    /** The start and size of the freed heap blocks, reused before growing the heap */
+    heap_free_list: Vec<(W, W)>,
+    /** The number of global addresses from `heap_start` the heap ever used */
+    heap_used: W,
+    /** The maximum number of slots stored by a single sub stack */
+    pub max_frame_size: usize,
+    /** The maximum number of slots stored by all the sub stacks and the global memory together */
+    pub max_total_memory: usize,
+    /** The number of rows `dump` writes for each memory before skipping the rest */
+    pub max_dump_rows: usize,
+    /** The slots whose writes are recorded */
+    watched_slots: Vec<WatchedSlot<W>>,
+    /** The writes to the watched slots, oldest first, until they are taken */
+    writes: Vec<SlotWrite<W>>,
+}
+impl<W: Word> Default for MemoryState<W> {
+    fn default() -> Self {
+        MemoryState::with_backing(MemoryBacking::default())
+    }
+}
+impl<W: Word> MemoryState<W> {
+    pub fn with_backing(backing: MemoryBacking) -> MemoryState<W> {
+        MemoryState {
+            backing,
+            stack: vec![],
+            global: Slots::new(backing),
+            heap_blocks: HashMap::new(),
+            heap_free_list: vec![],
+            heap_used: W::ZERO,
+            max_frame_size: 16 * 1024,
+            max_total_memory: 1024 * 1024,
+            max_dump_rows: 32,
+            watched_slots: vec![],
+            writes: vec![],
+        }
+    }
+
+    pub fn create_new_sub_stack(&mut self, return_address: u16) -> Result<(), MemoryError> {
+        self.create_new_sub_stack_with_data(return_address, vec![], W::ZERO)
+    }
+
+    /** Creates a sub stack starting with the provided data.
+    A non-zero size pre-allocates that many slots and makes the sub stack fixed-size */
+    pub fn create_new_sub_stack_with_data(
+        &mut self,
+        return_address: u16,
+        data: Vec<W>,
+        size: W,
+    ) -> Result<(), MemoryError> {
+        let mut data = Slots::from_vec(self.backing, data);
+        let size = if size > W::ZERO {
+            let size = size.max(W::from_usize(data.len()));
+            data.reserve(size.to_usize());
+            Some(size)
+        } else {
+            None
+        };
+        if !data.is_empty() {
+            self.check_growth(0, data.stored_len(), W::from_usize(data.len() - 1), true)?;
+        }
         let sub_stack = SubStack {
             return_address,
-            ..Default::default()
+            size,
+            data,
+            canary: 0,
+        };
+
+        self.stack.push(sub_stack);
+        Ok(())
+    }
+
+    /** The number of slots stored by all the sub stacks and the global memory */
+    pub fn get_total_memory(&self) -> usize {
+        self.stack
+            .iter()
+            .map(|sub_stack| sub_stack.data.stored_len())
+            .sum::<usize>()
+            + self.global.stored_len()
+    }
+
+    /** Checks the limits before storing `added` more slots in a memory up to the address, the memory being
+    a sub stack when `frame` is set, and the global memory otherwise. `stored` is its current number of slots */
+    fn check_growth(
+        &self,
+        stored: usize,
+        added: usize,
+        address: W,
+        frame: bool,
+    ) -> Result<(), MemoryError> {
+        if added == 0 {
+            return Ok(());
+        }
+        let address = address.to_u64();
+        if frame && stored + added > self.max_frame_size {
+            return Err(MemoryError::FrameLimitExceeded {
+                address,
+                limit: self.max_frame_size,
+            });
+        }
+        if self.get_total_memory() + added > self.max_total_memory {
+            return Err(MemoryError::MemoryLimitExceeded {
+                address,
+                limit: self.max_total_memory,
+            });
+        }
+
+        Ok(())
+    }
+
+    /** Checks the limits before writing the address of a sub stack */
+    fn check_sub_stack_growth(
+        &self,
+        sub_stack: &SubStack<W>,
+        address: W,
+    ) -> Result<(), MemoryError> {
+        sub_stack.check_bounds(address)?;
+        self.check_growth(
+            sub_stack.data.stored_len(),
+            sub_stack.data.growth(address),
+            address,
+            true,
+        )
+    }
+
+    /** Checks the bounds and the limits before writing `length` slots from the address of the current sub stack,
+    or of the caller's one when `caller` is set, so that none of them is written when one of them can't be */
+    pub fn check_sub_stack_range(
+        &self,
+        address: W,
+        length: W,
+        caller: bool,
+    ) -> Result<(), MemoryError> {
+        if length == W::ZERO {
+            return Ok(());
+        }
+        let sub_stack = if caller {
+            self.get_parent_sub_stack()
+                .ok_or(MemoryError::NoCallerFrame)?
+        } else {
+            self.get_current_sub_stack()
         };
+        let last_address = address.wrapping_add(length.wrapping_sub(W::ONE));
+        sub_stack.check_bounds(last_address)?;
+
+        self.check_growth(
+            sub_stack.data.stored_len(),
+            sub_stack.data.range_growth(address, length),
+            last_address,
+            true,
+        )
+    }
 
-        self.stack.insert(0, sub_stack);
+    /** Checks the limits before writing `length` slots from the address of the global memory */
+    pub fn check_global_range(&self, address: W, length: W) -> Result<(), MemoryError> {
+        if length == W::ZERO {
+            return Ok(());
+        }
+
+        self.check_growth(
+            self.global.stored_len(),
+            self.global.range_growth(address, length),
+            address.wrapping_add(length.wrapping_sub(W::ONE)),
+            false,
+        )
+    }
+
+    /** The number of sub stacks, which is the current call depth */
+    pub fn get_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /** Iterates over the sub stacks, from the current one to the one of the first function */
+    pub fn sub_stacks(&self) -> impl Iterator<Item = &SubStack<W>> {
+        self.stack.iter().rev()
+    }
+
+    pub fn get_current_sub_stack(&self) -> &SubStack<W> {
+        self.stack.last().expect("No sub stack")
+    }
+
+    pub fn get_current_sub_stack_mut(&mut self) -> &mut SubStack<W> {
+        self.stack.last_mut().expect("No sub stack")
     }
 
-    pub fn get_current_sub_stack(&self) -> &SubStack {
-        &self.stack[0]
+    /** The sub stack of the function that called the current one, None inside the first function */
+    pub fn get_parent_sub_stack(&self) -> Option<&SubStack<W>> {
+        let depth = self.stack.len();
+        depth.checked_sub(2).map(|index| &self.stack[index])
     }
 
-    pub fn get_current_sub_stack_mut(&mut self) -> &mut SubStack {
-        &mut self.stack[0]
+    pub fn get_parent_sub_stack_mut(&mut self) -> Option<&mut SubStack<W>> {
+        let depth = self.stack.len();
+        depth.checked_sub(2).map(|index| &mut self.stack[index])
     }
 
-    pub fn write_data(&mut self, address: u16, data: u16) {
-        let current_sub_stack = self.get_current_sub_stack_mut();
+    pub fn read_data(&self, address: W) -> Result<W, MemoryError> {
+        self.get_current_sub_stack().read_data(address)
+    }
+
+    pub fn write_data(&mut self, address: W, data: W) -> Result<(), MemoryError> {
+        let current_sub_stack = self.get_current_sub_stack();
+        self.check_sub_stack_growth(current_sub_stack, address)?;
+        let old_value = current_sub_stack.data.get(address);
+
+        self.get_current_sub_stack_mut().write_data(address, data)?;
+        self.record_write(WatchedSlot::Stack(address), old_value, data);
+        Ok(())
+    }
+
+    pub fn read_caller_data(&self, address: W) -> Result<W, MemoryError> {
+        self.get_parent_sub_stack()
+            .ok_or(MemoryError::NoCallerFrame)?
+            .read_data(address)
+    }
+
+    /** Grows the current sub stack with 0s until it holds at least `length` slots */
+    pub fn reserve_data(&mut self, length: W) -> Result<(), MemoryError> {
+        let current_sub_stack = self.get_current_sub_stack();
+        if length.to_usize() > current_sub_stack.data.len() {
+            let last_address = length.wrapping_sub(W::ONE);
+            current_sub_stack.check_bounds(last_address)?;
+            self.check_growth(
+                current_sub_stack.data.stored_len(),
+                current_sub_stack.data.growth_to_length(length.to_usize()),
+                last_address,
+                true,
+            )?;
+            self.get_current_sub_stack_mut()
+                .data
+                .reserve(length.to_usize());
+        }
+
+        Ok(())
+    }
 
-        if current_sub_stack.data.get(address as usize).is_none() {
-            while current_sub_stack.data.get(address as usize).is_none() {
-                current_sub_stack.data.push(0);
+    pub fn write_caller_data(&mut self, address: W, data: W) -> Result<(), MemoryError> {
+        let parent_sub_stack = self
+            .get_parent_sub_stack()
+            .ok_or(MemoryError::NoCallerFrame)?;
+        self.check_sub_stack_growth(parent_sub_stack, address)?;
+        let old_value = parent_sub_stack.data.get(address);
+
+        self.get_parent_sub_stack_mut()
+            .ok_or(MemoryError::NoCallerFrame)?
+            .write_data(address, data)?;
+        self.record_write(WatchedSlot::Stack(address), old_value, data);
+        Ok(())
+    }
+
+    /** Slots that were never written read as 0, without growing the global memory */
+    pub fn read_global(&self, address: W) -> Result<W, MemoryError> {
+        Ok(self.global.get(address))
+    }
+
+    pub fn write_global(&mut self, address: W, data: W) -> Result<(), MemoryError> {
+        self.check_growth(
+            self.global.stored_len(),
+            self.global.growth(address),
+            address,
+            false,
+        )?;
+        let old_value = self.global.get(address);
+        self.global.set(address, data);
+
+        self.record_write(WatchedSlot::Global(address), old_value, data);
+        Ok(())
+    }
+
+    /** Allocates a heap block of `size` slots (at least one) filled with 0s, and returns its global address.
+    The first freed block large enough is reused, otherwise the heap grows */
+    pub fn allocate(&mut self, size: W) -> Result<W, MemoryError> {
+        let size = size.max(W::ONE);
+        let address = match self
+            .heap_free_list
+            .iter()
+            .position(|&(_, free_size)| free_size >= size)
+        {
+            Some(index) => {
+                let (address, free_size) = self.heap_free_list[index];
+                if free_size == size {
+                    self.heap_free_list.remove(index);
+                } else {
+                    self.heap_free_list[index] =
+                        (address.wrapping_add(size), free_size.wrapping_sub(size));
+                }
+                address
             }
+            None => {
+                let heap_size = W::MAX.wrapping_sub(heap_start()).wrapping_add(W::ONE);
+                if size > heap_size.wrapping_sub(self.heap_used) {
+                    return Err(MemoryError::HeapExhausted {
+                        size: size.to_u64(),
+                    });
+                }
+                let address = heap_start::<W>().wrapping_add(self.heap_used);
+                let last_address = address.wrapping_add(size.wrapping_sub(W::ONE));
+                let added = match self.global {
+                    Slots::Dense(_) => self.global.growth(last_address),
+                    Slots::Sparse { .. } => size.to_usize(),
+                };
+                self.check_growth(self.global.stored_len(), added, last_address, false)?;
+                self.heap_used = self.heap_used.wrapping_add(size);
+                address
+            }
+        };
+
+        for offset in 0..size.to_u64() {
+            self.write_global(address.wrapping_add(W::from_u64(offset)), W::ZERO)?;
+        }
+        self.heap_blocks.insert(address, size);
+        Ok(address)
+    }
+
+    /** Releases the heap block starting at the address, so that a later allocation can reuse it */
+    pub fn free(&mut self, address: W) -> Result<(), MemoryError> {
+        let size = self
+            .heap_blocks
+            .remove(&address)
+            .ok_or(MemoryError::InvalidFree(address.to_u64()))?;
+
+        self.heap_free_list.push((address, size));
+        Ok(())
+    }
+
+    /** Writes every sub stack, from the current one to the first one, then the global memory,
+    as rows of 8 hexadecimal values each starting with the address of the first value.
+    Every value is padded to the number of hexadecimal digits of a word */
+    pub fn dump(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        for (index, sub_stack) in self.sub_stacks().enumerate() {
+            writeln!(
+                writer,
+                "Sub stack {index} (return address {}, {} slots)",
+                sub_stack.return_address,
+                sub_stack.data.len()
+            )?;
+            self.dump_slots(writer, &sub_stack.data)?;
         }
-        current_sub_stack.data[address as usize] = data;
+        writeln!(writer, "Global memory ({} slots)", self.global.len())?;
+        self.dump_slots(writer, &self.global)
+    }
+
+    fn dump_slots(&self, writer: &mut impl Write, slots: &Slots<W>) -> std::io::Result<()> {
+        if slots.is_empty() {
+            return writeln!(writer, "  (empty)");
+        }
+
+        let rows = slots.len().div_ceil(8);
+        for row in 0..rows.min(self.max_dump_rows) {
+            let start = row * 8;
+            let end = (start + 8).min(slots.len());
+            let values: Vec<String> = (start..end)
+                .map(|address| {
+                    let value = slots.get(W::from_usize(address));
+                    format!("{value:0width$x}", width = W::BITS.div_ceil(4) as usize)
+                })
+                .collect();
+            writeln!(writer, "  {start:04x}: {}", values.join(" "))?;
+        }
+        if rows > self.max_dump_rows {
+            writeln!(writer, "  ... {} more rows", rows - self.max_dump_rows)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn push_value(&mut self, value: W) -> Result<(), MemoryError> {
+        let address = W::from_usize(self.get_current_sub_stack().data.len());
+        self.check_sub_stack_growth(self.get_current_sub_stack(), address)?;
+
+        self.get_current_sub_stack_mut().data.push(value);
+        self.record_write(WatchedSlot::Stack(address), W::ZERO, value);
+        Ok(())
+    }
+
+    pub fn pop_value(&mut self) -> Option<W> {
+        self.get_current_sub_stack_mut().data.pop()
     }
 
     pub fn rewind_stack(&mut self) {
-        self.stack.remove(0);
+        self.stack.pop();
+    }
+
+    /** Records the writes to the slot from now on, until they are taken with `take_writes` */
+    pub fn watch(&mut self, slot: WatchedSlot<W>) {
+        if !self.watched_slots.contains(&slot) {
+            self.watched_slots.push(slot);
+        }
+    }
+
+    pub fn watched_slots(&self) -> &[WatchedSlot<W>] {
+        &self.watched_slots
+    }
+
+    /** The writes to the watched slots since the last call, oldest first */
+    pub fn take_writes(&mut self) -> Vec<SlotWrite<W>> {
+        std::mem::take(&mut self.writes)
+    }
+
+    fn record_write(&mut self, slot: WatchedSlot<W>, old_value: W, new_value: W) {
+        if self.watched_slots.contains(&slot) {
+            self.writes.push(SlotWrite {
+                slot,
+                old_value,
+                new_value,
+            });
+        }
     }
 }
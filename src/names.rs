@@ -0,0 +1,6 @@
+use std::borrow::Cow;
+
+/** The name of a function or a label in instructions.
+String literals are borrowed as they are, while names built at runtime (read from a file, parsed from text...)
+are owned by the instruction, so that nothing outlives the program using them. Both turn into a name with `into()` */
+pub type Name = Cow<'static, str>;
@@ -0,0 +1,44 @@
+/** A xorshift generator, small enough to be stored in the CPU state and replayed from a seed */
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // A xorshift generator stays stuck at 0, so that seed is replaced by an arbitrary non-zero one
+        let state = if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        };
+
+        Rng { state }
+    }
+
+    /** Seeds the generator from the current time */
+    pub fn from_time() -> Rng {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Rng::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /** Returns a value in [0, bound), or in the whole range of `bits` bits when the bound is 0 */
+    pub fn next_bounded(&mut self, bound: u64, bits: u32) -> u64 {
+        let value = self.next_u64();
+        if bound == 0 {
+            value >> (64 - bits)
+        } else {
+            value % bound
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::{Debug, Display, LowerHex};
+use std::hash::Hash;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+/** An unsigned integer the CPU can use as its word, which is the size of its registers,
+of its memory slots and of its addresses. Arithmetic wraps around at the size of the word */
+pub trait Word:
+    Copy
+    + Default
+    + Eq
+    + Ord
+    + Hash
+    + Debug
+    + Display
+    + LowerHex
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+    + 'static
+{
+    const BITS: u32;
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+
+    /** Keeps the low bits of the value that fit in the word */
+    fn from_u64(value: u64) -> Self;
+    /** None when the value doesn't fit in the word */
+    fn try_from_u64(value: u64) -> Option<Self>;
+    fn to_u64(self) -> u64;
+    /** The word read as a signed (two's complement) integer */
+    fn to_signed(self) -> i64;
+    /** The two's complement bit pattern of the value, None when it doesn't fit in the word read as signed */
+    fn from_signed(value: i64) -> Option<Self>;
+
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_neg(self) -> Self;
+    fn overflowing_add(self, other: Self) -> (Self, bool);
+    fn overflowing_sub(self, other: Self) -> (Self, bool);
+    fn overflowing_mul(self, other: Self) -> (Self, bool);
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn checked_rem(self, other: Self) -> Option<Self>;
+    fn checked_shl(self, amount: u32) -> Option<Self>;
+    fn checked_shr(self, amount: u32) -> Option<Self>;
+    fn rotate_left(self, amount: u32) -> Self;
+    fn rotate_right(self, amount: u32) -> Self;
+    fn count_ones(self) -> u32;
+    fn leading_zeros(self) -> u32;
+    fn trailing_zeros(self) -> u32;
+
+    /** Keeps the low bits of the value that fit in the word */
+    fn from_usize(value: usize) -> Self {
+        Self::from_u64(value as u64)
+    }
+
+    fn to_usize(self) -> usize {
+        self.to_u64() as usize
+    }
+
+    /** 1 when true, 0 when false */
+    fn from_bool(value: bool) -> Self {
+        if value { Self::ONE } else { Self::ZERO }
+    }
+}
+
+macro_rules! impl_word {
+    ($unsigned:ty, $signed:ty) => {
+        impl Word for $unsigned {
+            const BITS: u32 = <$unsigned>::BITS;
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const MAX: Self = <$unsigned>::MAX;
+
+            fn from_u64(value: u64) -> Self {
+                value as $unsigned
+            }
+            fn try_from_u64(value: u64) -> Option<Self> {
+                <$unsigned>::try_from(value).ok()
+            }
+            fn to_u64(self) -> u64 {
+                self as u64
+            }
+            fn to_signed(self) -> i64 {
+                self as $signed as i64
+            }
+            fn from_signed(value: i64) -> Option<Self> {
+                <$signed>::try_from(value)
+                    .ok()
+                    .map(|value| value as $unsigned)
+            }
+
+            fn wrapping_add(self, other: Self) -> Self {
+                <$unsigned>::wrapping_add(self, other)
+            }
+            fn wrapping_sub(self, other: Self) -> Self {
+                <$unsigned>::wrapping_sub(self, other)
+            }
+            fn wrapping_neg(self) -> Self {
+                <$unsigned>::wrapping_neg(self)
+            }
+            fn overflowing_add(self, other: Self) -> (Self, bool) {
+                <$unsigned>::overflowing_add(self, other)
+            }
+            fn overflowing_sub(self, other: Self) -> (Self, bool) {
+                <$unsigned>::overflowing_sub(self, other)
+            }
+            fn overflowing_mul(self, other: Self) -> (Self, bool) {
+                <$unsigned>::overflowing_mul(self, other)
+            }
+            fn saturating_add(self, other: Self) -> Self {
+                <$unsigned>::saturating_add(self, other)
+            }
+            fn saturating_sub(self, other: Self) -> Self {
+                <$unsigned>::saturating_sub(self, other)
+            }
+            fn checked_add(self, other: Self) -> Option<Self> {
+                <$unsigned>::checked_add(self, other)
+            }
+            fn checked_div(self, other: Self) -> Option<Self> {
+                <$unsigned>::checked_div(self, other)
+            }
+            fn checked_rem(self, other: Self) -> Option<Self> {
+                <$unsigned>::checked_rem(self, other)
+            }
+            fn checked_shl(self, amount: u32) -> Option<Self> {
+                <$unsigned>::checked_shl(self, amount)
+            }
+            fn checked_shr(self, amount: u32) -> Option<Self> {
+                <$unsigned>::checked_shr(self, amount)
+            }
+            fn rotate_left(self, amount: u32) -> Self {
+                <$unsigned>::rotate_left(self, amount)
+            }
+            fn rotate_right(self, amount: u32) -> Self {
+                <$unsigned>::rotate_right(self, amount)
+            }
+            fn count_ones(self) -> u32 {
+                <$unsigned>::count_ones(self)
+            }
+            fn leading_zeros(self) -> u32 {
+                <$unsigned>::leading_zeros(self)
+            }
+            fn trailing_zeros(self) -> u32 {
+                <$unsigned>::trailing_zeros(self)
+            }
+        }
+    };
+}
+
+impl_word!(u8, i8);
+impl_word!(u16, i16);
+impl_word!(u32, i32);
@@ -0,0 +1,473 @@
+mod common;
+
+use common::{fail_main, flags_after, load_main, reg, result_of, run_main};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, FLAG_CARRY, FLAG_NEGATIVE, FLAG_ZERO, InstructionArgument,
+    Register,
+};
+
+/** Runs "main" with 6 in the first stack slot and 7 in "a" before the instruction, and returns "res" */
+fn with_six_and_seven(instruction: CpuInstruction) -> u16 {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(6), InstructionArgument::Stack(0)),
+        CpuInstruction::Mov(InstructionArgument::Value(7), reg(Register::A)),
+        instruction,
+    ]);
+
+    cpu.get_register(Register::Res)
+}
+
+#[test]
+fn mul_multiplies_every_kind_of_argument() {
+    let sixes = [InstructionArgument::Stack(0), InstructionArgument::Value(6)];
+    let sevens = [reg(Register::A), InstructionArgument::Value(7)];
+    for six in sixes {
+        for seven in sevens {
+            assert_eq!(with_six_and_seven(CpuInstruction::Mul(six, seven)), 42);
+            assert_eq!(with_six_and_seven(CpuInstruction::Mul(seven, six)), 42);
+        }
+    }
+}
+
+#[test]
+fn mul_wraps_around_on_overflow() {
+    assert_eq!(
+        result_of(CpuInstruction::Mul(
+            InstructionArgument::Value(0x100),
+            InstructionArgument::Value(0x101)
+        )),
+        0x100
+    );
+}
+
+#[test]
+fn div_rounds_the_quotient_towards_zero() {
+    assert_eq!(
+        result_of(CpuInstruction::Div(
+            InstructionArgument::Value(43),
+            InstructionArgument::Value(5)
+        )),
+        8
+    );
+}
+
+#[test]
+fn div_accepts_every_kind_of_argument() {
+    let cases = [
+        (InstructionArgument::Value(42), reg(Register::A), 6),
+        (
+            InstructionArgument::Value(42),
+            InstructionArgument::Stack(0),
+            7,
+        ),
+        (
+            InstructionArgument::Stack(0),
+            InstructionArgument::Value(4),
+            1,
+        ),
+        (reg(Register::A), InstructionArgument::Value(2), 3),
+        (reg(Register::A), InstructionArgument::Stack(0), 1),
+        (InstructionArgument::Stack(0), reg(Register::A), 0),
+    ];
+    for (dividend, divisor, quotient) in cases {
+        assert_eq!(
+            with_six_and_seven(CpuInstruction::Div(dividend, divisor)),
+            quotient
+        );
+    }
+}
+
+#[test]
+fn dividing_by_zero_stops_the_cpu_at_the_instruction() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Div(InstructionArgument::Value(1), InstructionArgument::Value(0)),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error.inner(), CpuError::DivisionByZero));
+    assert!(error.to_string().contains("in main at instruction 1"));
+    assert_eq!(output.text(), "");
+    assert!(matches!(
+        fail_main(vec![CpuInstruction::Div(
+            InstructionArgument::Stack(0),
+            reg(Register::A)
+        )]),
+        CpuError::DivisionByZero
+    ));
+}
+
+#[test]
+fn mod_computes_the_remainder() {
+    assert_eq!(
+        with_six_and_seven(CpuInstruction::Mod(
+            reg(Register::A),
+            InstructionArgument::Stack(0)
+        )),
+        1
+    );
+    assert_eq!(
+        result_of(CpuInstruction::Mod(
+            InstructionArgument::Value(42),
+            InstructionArgument::Value(7)
+        )),
+        0
+    );
+    assert!(matches!(
+        fail_main(vec![CpuInstruction::Mod(
+            InstructionArgument::Value(3),
+            InstructionArgument::Value(0)
+        )]),
+        CpuError::DivisionByZero
+    ));
+}
+
+#[test]
+fn mod_tells_whether_a_counter_is_even() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Mod(reg(Register::A), InstructionArgument::Value(2)),
+        CpuInstruction::Eq(reg(Register::Res), InstructionArgument::Value(0)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(5)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+    ]);
+
+    assert_eq!(output, "1\n0\n1\n0\n1\n");
+}
+
+#[test]
+fn add_sets_the_carry_flag_when_it_wraps_around() {
+    let add = |first, second| {
+        flags_after(CpuInstruction::Add(
+            InstructionArgument::Value(first),
+            InstructionArgument::Value(second),
+        ))
+    };
+
+    assert_eq!(add(0xFFFF, 1), FLAG_ZERO | FLAG_CARRY);
+    assert_eq!(add(0xFFFF, 2), FLAG_CARRY);
+    assert_eq!(add(0x7FFF, 1), FLAG_NEGATIVE);
+    assert_eq!(add(1, 2), 0);
+}
+
+#[test]
+fn sub_sets_the_zero_flag_when_the_values_are_equal() {
+    let sub = |first, second| {
+        flags_after(CpuInstruction::Sub(
+            InstructionArgument::Value(first),
+            InstructionArgument::Value(second),
+        ))
+    };
+
+    assert_eq!(sub(5, 5), FLAG_ZERO);
+    assert_eq!(sub(5, 6), FLAG_NEGATIVE | FLAG_CARRY);
+    assert_eq!(sub(6, 5), 0);
+}
+
+#[test]
+fn adc_and_sbb_chain_32_bit_arithmetic_over_register_pairs() {
+    // (a, b) = 0x0001_FFFF and (c, d) = 0x0002_0001, high words first
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(0x0001), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(0xFFFF), reg(Register::B)),
+        CpuInstruction::Mov(InstructionArgument::Value(0x0002), reg(Register::C)),
+        CpuInstruction::Mov(InstructionArgument::Value(0x0001), reg(Register::D)),
+        CpuInstruction::Add(reg(Register::B), reg(Register::D)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+        CpuInstruction::Adc(reg(Register::A), reg(Register::C)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::A)),
+    ]);
+    assert_eq!(cpu.get_register(Register::A), 0x0004);
+    assert_eq!(cpu.get_register(Register::B), 0x0000);
+
+    // 0x0004_0000 - 0x0000_0001
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(0x0004), reg(Register::A)),
+        CpuInstruction::Sub(reg(Register::B), InstructionArgument::Value(1)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+        CpuInstruction::Sbb(reg(Register::A), InstructionArgument::Value(0)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::A)),
+    ]);
+    assert_eq!(cpu.get_register(Register::A), 0x0003);
+    assert_eq!(cpu.get_register(Register::B), 0xFFFF);
+}
+
+#[test]
+fn add_and_sub_wrap_around_and_continue() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Add(
+            InstructionArgument::Value(0xFFFF),
+            InstructionArgument::Value(1),
+        ),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Sub(InstructionArgument::Value(0), InstructionArgument::Value(1)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::A)),
+    ]);
+
+    assert_eq!(output, "0\n65535\n");
+    assert_eq!(cpu.get_register(Register::A), 1);
+}
+
+#[test]
+fn an_overflow_wraps_around_or_traps_depending_on_the_mode() {
+    let overflowing = || {
+        load_main(vec![
+            CpuInstruction::Add(
+                InstructionArgument::Value(0xFFFF),
+                InstructionArgument::Value(2),
+            ),
+            CpuInstruction::Print(reg(Register::Res)),
+        ])
+    };
+
+    let (mut cpu, output) = overflowing();
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "1\n");
+
+    let (mut cpu, output) = overflowing();
+    cpu.trap_on_overflow = true;
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Overflow {
+            first: 0xFFFF,
+            second: 2
+        }
+    ));
+    assert!(error.to_string().contains("in main at instruction 1"));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn saturating_arithmetic_clamps_at_both_boundaries() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Mov(
+            InstructionArgument::Value(0xFFF0),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::Mov(InstructionArgument::Value(0x20), reg(Register::A)),
+        CpuInstruction::SatAdd(InstructionArgument::Stack(0), reg(Register::A)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::SatAdd(reg(Register::A), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::SatSub(reg(Register::A), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::SatSub(InstructionArgument::Stack(0), reg(Register::A)),
+        CpuInstruction::Print(reg(Register::Res)),
+    ]);
+
+    assert_eq!(output, "65535\n65535\n0\n65488\n");
+    assert_eq!(cpu.get_register(Register::Res), 0xFFD0);
+}
+
+#[test]
+fn saturating_arithmetic_is_exact_at_the_boundaries() {
+    assert_eq!(
+        result_of(CpuInstruction::SatAdd(
+            InstructionArgument::Value(0xFFFE),
+            InstructionArgument::Value(1)
+        )),
+        0xFFFF
+    );
+    assert_eq!(
+        result_of(CpuInstruction::SatSub(
+            InstructionArgument::Value(1),
+            InstructionArgument::Value(1)
+        )),
+        0
+    );
+    let clamped = flags_after(CpuInstruction::SatSub(
+        InstructionArgument::Value(0),
+        InstructionArgument::Value(1),
+    ));
+    assert_eq!(clamped & FLAG_CARRY, FLAG_CARRY);
+}
+
+#[test]
+fn neg_and_abs_read_the_value_as_signed() {
+    let neg = |value| result_of(CpuInstruction::Neg(InstructionArgument::Value(value)));
+    let abs = |value| result_of(CpuInstruction::Abs(InstructionArgument::Value(value)));
+
+    assert_eq!(neg(0), 0);
+    assert_eq!(neg(5), 0xFFFB);
+    assert_eq!(neg(0xFFFB), 5);
+    assert_eq!(abs(5), 5);
+    assert_eq!(abs(0xFFFB), 5);
+    assert_eq!(abs(0), 0);
+    // The lowest signed value has no positive counterpart
+    assert_eq!(neg(0x8000), 0x8000);
+    assert_eq!(abs(0x8000), 0x8000);
+}
+
+#[test]
+fn mulwide_splits_the_product_between_res_and_d() {
+    let (cpu, _) = run_main(vec![CpuInstruction::MulWide(
+        InstructionArgument::Value(0xFFFF),
+        InstructionArgument::Value(0xFFFF),
+    )]);
+
+    assert_eq!(cpu.get_register(Register::Res), 0x0001);
+    assert_eq!(cpu.get_register(Register::D), 0xFFFE);
+}
+
+/** Runs "main" with the double-word values in the (a, b) and (c, d) register pairs before the instruction,
+and returns the double word left in (a, b) */
+fn with_register_pairs(first: u32, second: u32, instruction: CpuInstruction) -> u32 {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(
+            InstructionArgument::Value((first >> 16) as u16),
+            reg(Register::A),
+        ),
+        CpuInstruction::Mov(InstructionArgument::Value(first as u16), reg(Register::B)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value((second >> 16) as u16),
+            reg(Register::C),
+        ),
+        CpuInstruction::Mov(InstructionArgument::Value(second as u16), reg(Register::D)),
+        instruction,
+    ]);
+
+    (u32::from(cpu.get_register(Register::A)) << 16) | u32::from(cpu.get_register(Register::B))
+}
+
+#[test]
+fn add32_carries_the_low_word_into_the_high_word() {
+    assert_eq!(
+        with_register_pairs(0x0001_FFFF, 0x0002_0001, CpuInstruction::Add32()),
+        0x0004_0000
+    );
+    assert_eq!(
+        with_register_pairs(0xFFFF_FFFF, 0x0000_0002, CpuInstruction::Add32()),
+        0x0000_0001
+    );
+}
+
+#[test]
+fn sub32_borrows_from_the_high_word() {
+    assert_eq!(
+        with_register_pairs(0x0004_0000, 0x0000_0001, CpuInstruction::Sub32()),
+        0x0003_FFFF
+    );
+}
+
+#[test]
+fn signed_values_add_as_their_twos_complement() {
+    assert_eq!(
+        result_of(CpuInstruction::Add(
+            InstructionArgument::Value(5),
+            InstructionArgument::SignedValue(-3)
+        )),
+        2
+    );
+    assert_eq!(
+        result_of(CpuInstruction::Add(
+            InstructionArgument::Value(1),
+            InstructionArgument::SignedValue(-3)
+        )),
+        0xFFFE
+    );
+    assert_eq!(
+        result_of(CpuInstruction::Sub(
+            InstructionArgument::SignedValue(-1),
+            InstructionArgument::SignedValue(i16::MIN)
+        )),
+        0x7FFF
+    );
+}
+
+#[test]
+fn signed_values_show_their_sign_in_listings() {
+    let instruction: CpuInstruction = CpuInstruction::Add(
+        InstructionArgument::Value(5),
+        InstructionArgument::SignedValue(-3),
+    );
+
+    assert_eq!(format!("{instruction:?}"), "Add(Value(5), SignedValue(-3))");
+}
+
+#[test]
+fn inc_and_dec_update_their_target_in_place() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(41), reg(Register::A)),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(10),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::Dec(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::A), 42);
+    assert_eq!(cpu.get_register(Register::Res), 9);
+    assert_eq!(output, "9\n");
+}
+
+#[test]
+fn inc_and_dec_wrap_around_at_the_boundaries() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(0xFFFF), reg(Register::A)),
+        CpuInstruction::Inc(reg(Register::A)),
+    ]);
+    assert_eq!(cpu.get_register(Register::A), 0);
+    assert_eq!(cpu.get_register(Register::Res), 0);
+    assert_eq!(cpu.get_register(Register::Flags), FLAG_ZERO | FLAG_CARRY);
+
+    let (cpu, _) = run_main(vec![CpuInstruction::Dec(reg(Register::A))]);
+    assert_eq!(cpu.get_register(Register::A), 0xFFFF);
+    assert_eq!(cpu.get_register(Register::Res), 0xFFFF);
+    assert_eq!(
+        cpu.get_register(Register::Flags),
+        FLAG_NEGATIVE | FLAG_CARRY
+    );
+
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::A)),
+        CpuInstruction::Dec(reg(Register::A)),
+    ]);
+    assert_eq!(cpu.get_register(Register::Flags), FLAG_ZERO);
+}
+
+#[test]
+fn inc_traps_at_max_when_overflows_trap() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(0xFFFF), reg(Register::A)),
+        CpuInstruction::Inc(reg(Register::A)),
+    ]);
+    cpu.trap_on_overflow = true;
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Overflow {
+            first: 0xFFFF,
+            second: 1
+        }
+    ));
+    assert_eq!(cpu.get_register(Register::A), 0xFFFF);
+}
+
+#[test]
+fn inc_and_dec_reject_a_value_target_at_validation() {
+    let reject = |instruction| {
+        let mut cpu = Cpu16::new(0);
+        cpu.append_instructions(&[
+            CpuInstruction::Fn("main".into(), 0),
+            instruction,
+            CpuInstruction::Ret(),
+        ])
+        .unwrap_err()
+    };
+
+    assert!(matches!(
+        reject(CpuInstruction::Inc(InstructionArgument::Value(3))),
+        CpuError::InvalidDestination(3)
+    ));
+    assert!(matches!(
+        reject(CpuInstruction::Dec(InstructionArgument::SignedValue(-3))),
+        CpuError::InvalidDestination(-3)
+    ));
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use common::{fail_main, reg, result_of, run_main};
+use emulated_cpu::cpu::{CpuError, CpuInstruction, InstructionArgument, Register};
+
+/** Runs "main" with 0x1234 in the first stack slot and 0x00FF in "a" before the instruction,
+and returns "res" */
+fn with_stack_and_register(instruction: CpuInstruction) -> u16 {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(
+            InstructionArgument::Value(0x1234),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::Mov(InstructionArgument::Value(0x00FF), reg(Register::A)),
+        instruction,
+    ]);
+
+    cpu.get_register(Register::Res)
+}
+
+#[test]
+fn and_masks_off_the_low_byte() {
+    assert_eq!(
+        with_stack_and_register(CpuInstruction::And(
+            InstructionArgument::Stack(0),
+            reg(Register::A)
+        )),
+        0x0034
+    );
+    assert_eq!(
+        with_stack_and_register(CpuInstruction::And(
+            InstructionArgument::Stack(0),
+            InstructionArgument::Value(0xFF00)
+        )),
+        0x1200
+    );
+}
+
+#[test]
+fn or_sets_the_bits_of_either_value() {
+    assert_eq!(
+        with_stack_and_register(CpuInstruction::Or(
+            reg(Register::A),
+            InstructionArgument::Stack(0)
+        )),
+        0x12FF
+    );
+}
+
+#[test]
+fn xor_toggles_a_bit() {
+    let toggle = |value| {
+        result_of(CpuInstruction::Xor(
+            InstructionArgument::Value(value),
+            InstructionArgument::Value(0b100),
+        ))
+    };
+
+    assert_eq!(toggle(0b1001), 0b1101);
+    assert_eq!(toggle(0b1101), 0b1001);
+    assert_eq!(
+        with_stack_and_register(CpuInstruction::Xor(
+            InstructionArgument::Stack(0),
+            InstructionArgument::Stack(0)
+        )),
+        0
+    );
+}
+
+#[test]
+fn not_inverts_every_bit() {
+    assert_eq!(
+        result_of(CpuInstruction::Not(InstructionArgument::Value(0))),
+        0xFFFF
+    );
+    assert_eq!(
+        with_stack_and_register(CpuInstruction::Not(reg(Register::A))),
+        0xFF00
+    );
+    assert_eq!(
+        with_stack_and_register(CpuInstruction::Not(InstructionArgument::Stack(0))),
+        0xEDCB
+    );
+}
+
+#[test]
+fn shifts_move_the_bits_by_the_amount() {
+    let shl = |value, amount| {
+        result_of(CpuInstruction::Shl(
+            InstructionArgument::Value(value),
+            InstructionArgument::Value(amount),
+        ))
+    };
+    let shr = |value, amount| {
+        result_of(CpuInstruction::Shr(
+            InstructionArgument::Value(value),
+            InstructionArgument::Value(amount),
+        ))
+    };
+
+    assert_eq!(shl(0x1234, 0), 0x1234);
+    assert_eq!(shr(0x1234, 0), 0x1234);
+    assert_eq!(shl(0x0003, 15), 0x8000);
+    assert_eq!(shr(0xC000, 15), 0x0001);
+}
+
+#[test]
+fn shifting_by_the_word_size_or_more_gives_0() {
+    for amount in [16, 17, 0xFFFF] {
+        assert_eq!(
+            result_of(CpuInstruction::Shl(
+                InstructionArgument::Value(0xFFFF),
+                InstructionArgument::Value(amount)
+            )),
+            0
+        );
+        assert_eq!(
+            result_of(CpuInstruction::Shr(
+                InstructionArgument::Value(0xFFFF),
+                InstructionArgument::Value(amount)
+            )),
+            0
+        );
+    }
+}
+
+#[test]
+fn rotating_by_the_word_size_is_the_identity() {
+    for rotation in [CpuInstruction::Rol, CpuInstruction::Ror] {
+        assert_eq!(
+            result_of(rotation(
+                InstructionArgument::Value(0x1234),
+                InstructionArgument::Value(16)
+            )),
+            0x1234
+        );
+    }
+}
+
+#[test]
+fn rotated_bits_come_back_on_the_other_side() {
+    assert_eq!(
+        result_of(CpuInstruction::Rol(
+            InstructionArgument::Value(0x8001),
+            InstructionArgument::Value(1)
+        )),
+        0x0003
+    );
+    assert_eq!(
+        result_of(CpuInstruction::Ror(
+            InstructionArgument::Value(0x0003),
+            InstructionArgument::Value(17)
+        )),
+        0x8001
+    );
+}
+
+#[test]
+fn bit_counts_cover_zero_all_ones_and_single_bits() {
+    let count = |instruction: fn(InstructionArgument) -> CpuInstruction, value| {
+        result_of(instruction(InstructionArgument::Value(value)))
+    };
+
+    assert_eq!(count(CpuInstruction::Popcount, 0), 0);
+    assert_eq!(count(CpuInstruction::Popcount, 0xFFFF), 16);
+    assert_eq!(count(CpuInstruction::Popcount, 0x0100), 1);
+    assert_eq!(count(CpuInstruction::Clz, 0), 16);
+    assert_eq!(count(CpuInstruction::Clz, 0xFFFF), 0);
+    assert_eq!(count(CpuInstruction::Clz, 1), 15);
+    assert_eq!(count(CpuInstruction::Clz, 0x8000), 0);
+    assert_eq!(count(CpuInstruction::Ctz, 0), 16);
+    assert_eq!(count(CpuInstruction::Ctz, 0xFFFF), 0);
+    assert_eq!(count(CpuInstruction::Ctz, 1), 0);
+    assert_eq!(count(CpuInstruction::Ctz, 0x8000), 15);
+}
+
+#[test]
+fn setbit_and_clearbit_change_the_target_in_place() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(
+            InstructionArgument::Value(0b1000),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::SetBit(InstructionArgument::Stack(0), InstructionArgument::Value(0)),
+        CpuInstruction::ClearBit(InstructionArgument::Stack(0), InstructionArgument::Value(3)),
+        CpuInstruction::SetBit(reg(Register::A), InstructionArgument::Value(15)),
+        CpuInstruction::Mov(InstructionArgument::Stack(0), reg(Register::B)),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::B), 0b0001);
+    assert_eq!(cpu.get_register(Register::A), 0x8000);
+    assert_eq!(cpu.get_register(Register::Res), 0x8000);
+}
+
+#[test]
+fn bit_indexes_past_the_word_size_wrap_around() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::SetBit(reg(Register::A), InstructionArgument::Value(17)),
+        CpuInstruction::TestBit(reg(Register::A), InstructionArgument::Value(1)),
+    ]);
+    assert_eq!(cpu.get_register(Register::A), 0b10);
+    assert_eq!(cpu.get_register(Register::Res), 1);
+
+    let test_bit = |index| {
+        result_of(CpuInstruction::TestBit(
+            InstructionArgument::Value(0b100),
+            InstructionArgument::Value(index),
+        ))
+    };
+    assert_eq!(test_bit(2), 1);
+    assert_eq!(test_bit(3), 0);
+    assert_eq!(test_bit(18), 1);
+}
+
+#[test]
+fn setbit_and_clearbit_reject_a_value_target() {
+    for instruction in [CpuInstruction::SetBit, CpuInstruction::ClearBit] {
+        assert!(matches!(
+            fail_main(vec![instruction(
+                InstructionArgument::Value(4),
+                InstructionArgument::Value(0)
+            )]),
+            CpuError::InvalidDestination(4)
+        ));
+    }
+}
+
+#[test]
+fn byte_extensions_fill_the_high_byte() {
+    let sext8 = |value| result_of(CpuInstruction::Sext8(InstructionArgument::Value(value)));
+    let zext8 = |value| result_of(CpuInstruction::Zext8(InstructionArgument::Value(value)));
+
+    assert_eq!(sext8(0x7F), 0x007F);
+    assert_eq!(sext8(0x80), 0xFF80);
+    assert_eq!(sext8(0xFF), 0xFFFF);
+    assert_eq!(zext8(0x7F), 0x007F);
+    assert_eq!(zext8(0x80), 0x0080);
+    assert_eq!(zext8(0xFF), 0x00FF);
+    // Only the low byte of the value is read
+    assert_eq!(sext8(0x1280), 0xFF80);
+    assert_eq!(zext8(0x12FF), 0x00FF);
+}
@@ -0,0 +1,78 @@
+/*! Helpers shared by the integration tests, not every test file uses all of them */
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use emulated_cpu::cpu::{CpuInstruction, CpuState, InstructionArgument, Register, Word};
+
+/** An output the test keeps a handle on, to read what the program printed */
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+impl SharedOutput {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/** A CPU running as fast as possible with the instructions loaded, and a handle on its output */
+pub fn load<W: Word>(instructions: &[CpuInstruction<W>]) -> (CpuState<W>, SharedOutput) {
+    let output = SharedOutput::default();
+    let mut cpu = CpuState::with_output(0, Box::new(output.clone()));
+    cpu.append_instructions(instructions).unwrap();
+
+    (cpu, output)
+}
+
+/** Loads a "main" function made of the instructions followed by a RET */
+pub fn load_main(body: Vec<CpuInstruction>) -> (CpuState, SharedOutput) {
+    let mut instructions = vec![CpuInstruction::Fn("main".into(), 0)];
+    instructions.extend(body);
+    instructions.push(CpuInstruction::Ret());
+
+    load(&instructions)
+}
+
+/** Runs a "main" function made of the instructions, it must not fail */
+pub fn run_main(body: Vec<CpuInstruction>) -> (CpuState, String) {
+    let (mut cpu, output) = load_main(body);
+    cpu.execute().unwrap();
+
+    (cpu, output.text())
+}
+
+/** Runs a "main" function made of the instructions, which must fail, and returns the error */
+pub fn fail_main(body: Vec<CpuInstruction>) -> emulated_cpu::cpu::CpuError {
+    let (mut cpu, _) = load_main(body);
+
+    cpu.execute().unwrap_err().inner().clone()
+}
+
+/** Runs "main" applying the instruction, and returns "res" */
+pub fn result_of(instruction: CpuInstruction) -> u16 {
+    let (cpu, _) = run_main(vec![instruction]);
+
+    cpu.get_register(Register::Res)
+}
+
+/** Runs "main" applying the instruction, and returns "flags" */
+pub fn flags_after(instruction: CpuInstruction) -> u16 {
+    let (cpu, _) = run_main(vec![instruction]);
+
+    cpu.get_register(Register::Flags)
+}
+
+pub fn reg<W: Word>(register: Register) -> InstructionArgument<W> {
+    InstructionArgument::Register(register)
+}
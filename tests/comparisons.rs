@@ -0,0 +1,149 @@
+mod common;
+
+use common::{flags_after, result_of, run_main};
+use emulated_cpu::cpu::{
+    CpuInstruction, FLAG_CARRY, FLAG_NEGATIVE, FLAG_ZERO, InstructionArgument, Register,
+};
+
+/** Runs "main" with 5 in the first stack slot before the instruction, and returns "res" */
+fn with_five_on_the_stack(instruction: CpuInstruction) -> u16 {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(0)),
+        instruction,
+    ]);
+
+    cpu.get_register(Register::Res)
+}
+
+/** A variant of `CpuInstruction` comparing two values */
+type Comparison = fn(InstructionArgument, InstructionArgument) -> CpuInstruction;
+
+/** The "res" left by the comparison of the two values */
+fn compare(comparison: Comparison, first: u16, second: u16) -> u16 {
+    result_of(comparison(
+        InstructionArgument::Value(first),
+        InstructionArgument::Value(second),
+    ))
+}
+
+#[test]
+fn neq_tells_whether_the_values_differ() {
+    assert_eq!(compare(CpuInstruction::Neq, 3, 3), 0);
+    assert_eq!(compare(CpuInstruction::Neq, 3, 4), 1);
+    assert_eq!(
+        with_five_on_the_stack(CpuInstruction::Neq(
+            InstructionArgument::Stack(0),
+            InstructionArgument::Value(5)
+        )),
+        0
+    );
+    assert_eq!(
+        with_five_on_the_stack(CpuInstruction::Neq(
+            InstructionArgument::Value(6),
+            InstructionArgument::Stack(0)
+        )),
+        1
+    );
+}
+
+#[test]
+fn ordered_comparisons_follow_the_unsigned_order() {
+    let comparisons: [(Comparison, [u16; 3]); 4] = [
+        (CpuInstruction::Lt, [1, 0, 0]),
+        (CpuInstruction::Gt, [0, 0, 1]),
+        (CpuInstruction::Le, [1, 1, 0]),
+        (CpuInstruction::Ge, [0, 1, 1]),
+    ];
+    for (comparison, [less, equal, greater]) in comparisons {
+        assert_eq!(compare(comparison, 2, 3), less);
+        assert_eq!(compare(comparison, 3, 3), equal);
+        assert_eq!(compare(comparison, 4, 3), greater);
+    }
+}
+
+#[test]
+fn lt_bounds_a_loop() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Inc(InstructionArgument::Stack(0)),
+        CpuInstruction::Lt(
+            InstructionArgument::Stack(0),
+            InstructionArgument::Value(10),
+        ),
+        CpuInstruction::If(
+            InstructionArgument::Register(Register::Res),
+            Box::new(CpuInstruction::GotoLabel("loop".into())),
+            Box::new(CpuInstruction::Print(InstructionArgument::Stack(0))),
+        ),
+    ]);
+
+    assert_eq!(output, "10\n");
+}
+
+#[test]
+fn signed_comparisons_read_the_values_as_signed() {
+    let comparisons: [(Comparison, Comparison); 4] = [
+        (CpuInstruction::Slt, CpuInstruction::Lt),
+        (CpuInstruction::Sgt, CpuInstruction::Gt),
+        (CpuInstruction::Sle, CpuInstruction::Le),
+        (CpuInstruction::Sge, CpuInstruction::Ge),
+    ];
+    // 0x7FFF is the highest signed value and 0x8000 the lowest, 0xFFFF is -1
+    for (signed, unsigned) in comparisons {
+        for (first, second) in [(0x7FFF, 0x8000), (0xFFFF, 0x0001), (0x0001, 0xFFFF)] {
+            assert_ne!(
+                compare(signed, first, second),
+                compare(unsigned, first, second)
+            );
+        }
+        assert_eq!(
+            compare(signed, 0x8001, 0x8001),
+            compare(unsigned, 0x8001, 0x8001)
+        );
+    }
+    assert_eq!(compare(CpuInstruction::Slt, 0xFFFF, 0x0001), 1);
+    assert_eq!(compare(CpuInstruction::Sgt, 0x7FFF, 0x8000), 1);
+    assert_eq!(compare(CpuInstruction::Sge, 0x0001, 0xFFFF), 1);
+}
+
+#[test]
+fn cmp_sets_the_flags_without_touching_res() {
+    let cmp = |first, second| {
+        flags_after(CpuInstruction::Cmp(
+            InstructionArgument::Value(first),
+            InstructionArgument::Value(second),
+        ))
+    };
+
+    assert_eq!(cmp(3, 3), FLAG_ZERO);
+    assert_eq!(cmp(2, 3), FLAG_NEGATIVE | FLAG_CARRY);
+    assert_eq!(cmp(4, 3), 0);
+    // The negative flag follows the signed order and the carry flag the unsigned one
+    assert_eq!(cmp(0xFFFF, 1), FLAG_NEGATIVE);
+    assert_eq!(cmp(1, 0xFFFF), FLAG_CARRY);
+    assert_eq!(
+        result_of(CpuInstruction::Cmp(
+            InstructionArgument::Value(2),
+            InstructionArgument::Value(3)
+        )),
+        0
+    );
+}
+
+#[test]
+fn min_and_max_follow_the_unsigned_order() {
+    assert_eq!(compare(CpuInstruction::Min, 3, 9), 3);
+    assert_eq!(compare(CpuInstruction::Min, 9, 3), 3);
+    assert_eq!(compare(CpuInstruction::Max, 3, 9), 9);
+    assert_eq!(compare(CpuInstruction::Max, 9, 3), 9);
+    assert_eq!(compare(CpuInstruction::Min, 7, 7), 7);
+    assert_eq!(compare(CpuInstruction::Max, 7, 7), 7);
+    assert_eq!(compare(CpuInstruction::Min, 0xFFFF, 1), 1);
+    assert_eq!(
+        with_five_on_the_stack(CpuInstruction::Max(
+            InstructionArgument::Stack(0),
+            InstructionArgument::Value(4)
+        )),
+        5
+    );
+}
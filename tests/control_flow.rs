@@ -0,0 +1,554 @@
+mod common;
+
+use common::{fail_main, load, load_main, reg, run_main};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, CpuStatus, ExitReason, InstructionArgument, Register,
+};
+use emulated_cpu::names::Name;
+
+/** Jumps over "print 2" when the condition holds, the jump target being address 3 */
+fn guarded_jump(jump: CpuInstruction) -> String {
+    let (_, output) = run_main(vec![
+        jump,
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Print(InstructionArgument::Value(3)),
+        CpuInstruction::Print(InstructionArgument::Value(4)),
+    ]);
+
+    output
+}
+
+#[test]
+fn goto_if_executes_its_target_next_when_the_condition_holds() {
+    let output = guarded_jump(CpuInstruction::GotoIf(InstructionArgument::Value(1), 3));
+
+    assert_eq!(output, "3\n4\n");
+}
+
+#[test]
+fn goto_if_continues_normally_otherwise() {
+    let output = guarded_jump(CpuInstruction::GotoIf(InstructionArgument::Value(0), 3));
+
+    assert_eq!(output, "2\n3\n4\n");
+}
+
+#[test]
+fn goto_executes_its_target_next() {
+    let output = guarded_jump(CpuInstruction::Goto(3));
+
+    assert_eq!(output, "3\n4\n");
+}
+
+#[test]
+fn goto_out_of_the_program_stops_the_cpu() {
+    let error = fail_main(vec![CpuInstruction::Goto(100)]);
+
+    assert!(matches!(error, CpuError::JumpOutOfBounds(100)));
+}
+
+#[test]
+fn goto_if_behaves_like_an_if_around_a_goto() {
+    for condition in [0, 1] {
+        let goto_if = guarded_jump(CpuInstruction::GotoIf(
+            InstructionArgument::Value(condition),
+            3,
+        ));
+        let if_goto = guarded_jump(CpuInstruction::If(
+            InstructionArgument::Value(condition),
+            Box::new(CpuInstruction::Goto(3)),
+            Box::new(CpuInstruction::Nop()),
+        ));
+
+        assert_eq!(goto_if, if_goto);
+    }
+}
+
+/** Prints the case selected by the selector, 99 being the default case */
+fn switch(selector: u16) -> String {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Switch(InstructionArgument::Value(selector), vec![4, 6]),
+        CpuInstruction::Print(InstructionArgument::Value(99)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Print(InstructionArgument::Value(0)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    output.text()
+}
+
+#[test]
+fn switch_executes_the_case_of_the_selector() {
+    assert_eq!(switch(0), "0\n");
+    // The last entry of the table
+    assert_eq!(switch(1), "1\n");
+}
+
+#[test]
+fn switch_continues_normally_when_the_selector_is_out_of_the_table() {
+    assert_eq!(switch(2), "99\n");
+    assert_eq!(switch(u16::MAX), "99\n");
+}
+
+#[test]
+fn a_rejected_batch_of_instructions_is_not_loaded() {
+    let mut cpu = Cpu16::new(0);
+
+    let error = cpu
+        .append_instructions(&[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::Switch(InstructionArgument::Value(0), vec![3]),
+            CpuInstruction::Ret(),
+        ])
+        .unwrap_err();
+    assert!(matches!(error, CpuError::JumpOutOfBounds(3)));
+    let error = cpu
+        .append_instructions(&[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::GotoLabel("nowhere".into()),
+        ])
+        .unwrap_err();
+    assert!(matches!(error, CpuError::UnknownLabel(_)));
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error, CpuError::UnknownFunction(name) if name == "main"));
+}
+
+#[test]
+fn an_if_block_runs_every_instruction_of_its_branch() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::IfBlock(
+            InstructionArgument::Value(1),
+            vec![
+                CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(0)),
+                CpuInstruction::Mov(InstructionArgument::Value(2), InstructionArgument::Stack(1)),
+                CpuInstruction::Mov(InstructionArgument::Value(3), InstructionArgument::Stack(2)),
+            ],
+            vec![CpuInstruction::Mov(
+                InstructionArgument::Value(9),
+                InstructionArgument::Stack(0),
+            )],
+        ),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(1)),
+        CpuInstruction::Print(InstructionArgument::Stack(2)),
+    ]);
+
+    assert_eq!(output, "1\n2\n3\n");
+}
+
+#[test]
+fn a_ret_in_an_if_block_ends_the_block() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Print(InstructionArgument::Value(7)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::IfBlock(
+            InstructionArgument::Value(0),
+            vec![],
+            vec![
+                CpuInstruction::Ret(),
+                CpuInstruction::Print(InstructionArgument::Value(999)),
+            ],
+        ),
+        CpuInstruction::Print(InstructionArgument::Value(998)),
+        CpuInstruction::Ret(),
+    ]);
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "7\n");
+}
+
+#[test]
+fn returning_to_an_if_block_resumes_the_block_of_that_call() {
+    // Every call returns into the IF BLOCK of its own caller, at the same address for all of them
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(2), reg(Register::A)),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Print(InstructionArgument::Value(7)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::IfBlock(
+            reg(Register::A),
+            vec![
+                CpuInstruction::Dec(reg(Register::A)),
+                CpuInstruction::Call("f".into()),
+                CpuInstruction::Print(InstructionArgument::Value(998)),
+            ],
+            vec![
+                CpuInstruction::Ret(),
+                CpuInstruction::Print(InstructionArgument::Value(999)),
+            ],
+        ),
+        CpuInstruction::Ret(),
+    ]);
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "998\n998\n7\n");
+}
+
+/** Loads "main" running the block, followed by "print 3", and "f" printing 9 */
+fn load_block_calling(block: Vec<CpuInstruction>) -> (Cpu16, common::SharedOutput) {
+    load(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::IfBlock(InstructionArgument::Value(1), block, vec![]),
+        CpuInstruction::Print(InstructionArgument::Value(3)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(9)),
+        CpuInstruction::Ret(),
+    ])
+}
+
+#[test]
+fn a_function_called_from_an_if_block_returns_into_it() {
+    let (mut cpu, output) = load_block_calling(vec![
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+    ]);
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "1\n9\n2\n3\n");
+}
+
+#[test]
+fn a_function_called_from_a_nested_if_block_returns_into_both_blocks() {
+    let (mut cpu, output) = load_block_calling(vec![
+        CpuInstruction::IfBlock(
+            InstructionArgument::Value(1),
+            vec![
+                CpuInstruction::Call("f".into()),
+                CpuInstruction::Print(InstructionArgument::Value(1)),
+            ],
+            vec![],
+        ),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+    ]);
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "9\n1\n2\n3\n");
+}
+
+#[test]
+fn an_interrupt_raised_in_an_if_block_returns_into_it() {
+    let (mut cpu, output) = load_block_calling(vec![
+        CpuInstruction::Int(InstructionArgument::Value(1)),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+    ]);
+    cpu.set_interrupt_handler(1, "f");
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "9\n2\n3\n");
+}
+
+#[test]
+fn a_breakpoint_in_an_if_block_resumes_inside_it() {
+    let (mut cpu, output) = load_block_calling(vec![
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Brk(),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+    ]);
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(report.exit, ExitReason::Paused { .. }));
+    assert_eq!(output.text(), "1\n");
+    let paused = cpu.snapshot();
+
+    cpu.resume().unwrap();
+    assert_eq!(output.text(), "1\n2\n3\n");
+
+    // The rest of the block is part of the snapshot
+    cpu.restore(&paused);
+    cpu.resume().unwrap();
+    assert_eq!(output.text(), "1\n2\n3\n2\n3\n");
+}
+
+#[test]
+fn if_blocks_can_be_nested() {
+    let (_, output) = run_main(vec![CpuInstruction::IfBlock(
+        InstructionArgument::Value(1),
+        vec![
+            CpuInstruction::Print(InstructionArgument::Value(1)),
+            CpuInstruction::IfBlock(
+                InstructionArgument::Value(0),
+                vec![CpuInstruction::Print(InstructionArgument::Value(99))],
+                vec![CpuInstruction::Print(InstructionArgument::Value(2))],
+            ),
+            CpuInstruction::Print(InstructionArgument::Value(3)),
+        ],
+        vec![],
+    )]);
+
+    assert_eq!(output, "1\n2\n3\n");
+}
+
+#[test]
+fn a_backward_jump_forms_a_loop() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(3)),
+        CpuInstruction::IfThen(reg(Register::Res), Box::new(CpuInstruction::Jump(-2))),
+        CpuInstruction::Print(reg(Register::A)),
+    ]);
+
+    assert_eq!(output, "3\n");
+}
+
+#[test]
+fn a_forward_jump_skips_over_an_instruction() {
+    let output = guarded_jump(CpuInstruction::Jump(2));
+
+    assert_eq!(output, "3\n4\n");
+    assert_eq!(guarded_jump(CpuInstruction::Jump(1)), "2\n3\n4\n");
+}
+
+#[test]
+fn jumping_out_of_the_program_stops_the_cpu() {
+    assert!(matches!(
+        fail_main(vec![CpuInstruction::Jump(-2)]),
+        CpuError::JumpOutOfBounds(-1)
+    ));
+    assert!(matches!(
+        fail_main(vec![CpuInstruction::Jump(100)]),
+        CpuError::JumpOutOfBounds(101)
+    ));
+}
+
+/** Jumps over "print 2" to the "skip" label when the jump is taken */
+fn jump_to_label(jump: fn(InstructionArgument, Name) -> CpuInstruction, condition: u16) -> String {
+    let (_, output) = run_main(vec![
+        jump(InstructionArgument::Value(condition), "skip".into()),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Label("skip".into()),
+        CpuInstruction::Print(InstructionArgument::Value(3)),
+    ]);
+
+    output
+}
+
+#[test]
+fn jz_jumps_only_when_the_condition_is_0() {
+    assert_eq!(jump_to_label(CpuInstruction::Jz, 0), "3\n");
+    assert_eq!(jump_to_label(CpuInstruction::Jz, 1), "2\n3\n");
+}
+
+#[test]
+fn jnz_jumps_only_when_the_condition_is_not_0() {
+    assert_eq!(jump_to_label(CpuInstruction::Jnz, 5), "3\n");
+    assert_eq!(jump_to_label(CpuInstruction::Jnz, 0), "2\n3\n");
+}
+
+#[test]
+fn goto_label_jumps_like_a_taken_jnz() {
+    let goto_label = |_, label_name| CpuInstruction::GotoLabel(label_name);
+    assert_eq!(jump_to_label(goto_label, 0), "3\n");
+
+    let cycles = |jump: CpuInstruction| {
+        let (cpu, _) = run_main(vec![
+            jump,
+            CpuInstruction::Print(InstructionArgument::Value(2)),
+            CpuInstruction::Label("skip".into()),
+        ]);
+
+        cpu.get_cycles()
+    };
+    assert_eq!(
+        cycles(CpuInstruction::GotoLabel("skip".into())),
+        cycles(CpuInstruction::Jnz(
+            InstructionArgument::Value(1),
+            "skip".into()
+        ))
+    );
+}
+
+#[test]
+fn loop_runs_its_body_once_per_count() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(0)),
+        CpuInstruction::Label("body".into()),
+        CpuInstruction::Add(reg(Register::B), InstructionArgument::Value(3)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+        CpuInstruction::Loop(InstructionArgument::Stack(0), "body".into()),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::B), 15);
+    assert_eq!(output, "0\n");
+}
+
+#[test]
+fn loop_rejects_a_value_counter() {
+    assert!(matches!(
+        fail_main(vec![
+            CpuInstruction::Label("body".into()),
+            CpuInstruction::Loop(InstructionArgument::Value(3), "body".into()),
+        ]),
+        CpuError::InvalidDestination(3)
+    ));
+}
+
+#[test]
+fn a_false_if_then_leaves_every_register_and_slot_untouched() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(3), InstructionArgument::Stack(0)),
+        CpuInstruction::Add(InstructionArgument::Stack(0), InstructionArgument::Value(4)),
+        CpuInstruction::IfThen(
+            InstructionArgument::Value(0),
+            Box::new(CpuInstruction::Mov(
+                InstructionArgument::Value(9),
+                InstructionArgument::Stack(0),
+            )),
+        ),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+    ]);
+    cpu.start().unwrap();
+    while cpu.get_register(Register::Ip) != 3 {
+        assert!(cpu.step());
+    }
+
+    let before = cpu.snapshot();
+    assert!(cpu.step());
+    let after = cpu.snapshot();
+    assert_eq!(before.registers(), after.registers());
+    assert_eq!(before.memory(), after.memory());
+
+    while cpu.step() {}
+    assert_eq!(output.text(), "3\n");
+}
+
+#[test]
+fn if_with_both_branches_still_runs_one_of_them() {
+    for (condition, expected) in [(1, "1\n"), (0, "2\n")] {
+        let (_, output) = run_main(vec![CpuInstruction::If(
+            InstructionArgument::Value(condition),
+            Box::new(CpuInstruction::Print(InstructionArgument::Value(1))),
+            Box::new(CpuInstruction::Print(InstructionArgument::Value(2))),
+        )]);
+
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn halting_reports_the_code() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Halt(InstructionArgument::Value(7))]);
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(
+        report.exit,
+        ExitReason::Halted {
+            code: 7,
+            address: 1
+        }
+    ));
+}
+
+#[test]
+fn halting_inside_an_if_stops_before_the_next_instruction() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::If(
+            InstructionArgument::Value(1),
+            Box::new(CpuInstruction::Halt(InstructionArgument::Value(3))),
+            Box::new(CpuInstruction::Nop()),
+        ),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+    ]);
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(
+        report.exit,
+        ExitReason::Halted {
+            code: 3,
+            address: 2
+        }
+    ));
+    assert!(matches!(cpu.get_status(), CpuStatus::Halted { .. }));
+    assert_eq!(output.text(), "1\n");
+}
+
+#[test]
+fn halting_inside_a_called_function_stops_the_caller_too() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Halt(InstructionArgument::Value(4)),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ]);
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(
+        report.exit,
+        ExitReason::Halted {
+            code: 4,
+            address: 5
+        }
+    ));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn a_passing_assert_does_nothing() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Add(InstructionArgument::Value(2), InstructionArgument::Value(3)),
+        CpuInstruction::Assert(reg(Register::Res), InstructionArgument::Value(5)),
+        CpuInstruction::Print(reg(Register::Res)),
+    ]);
+
+    assert_eq!(output, "5\n");
+    assert_eq!(cpu.get_register(Register::Res), 5);
+}
+
+#[test]
+fn a_failing_assert_stops_the_cpu_with_both_values() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Assert(InstructionArgument::Value(4), InstructionArgument::Value(5)),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::AssertionFailed {
+            actual: 4,
+            expected: 5
+        }
+    ));
+    assert!(error.to_string().contains("in main at instruction 1"));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn a_failing_assert_in_a_called_function_reports_the_call() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("check".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("check".into(), 0),
+        CpuInstruction::Assert(InstructionArgument::Value(0), InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::AssertionFailed {
+            actual: 0,
+            expected: 1
+        }
+    ));
+    let message = error.to_string();
+    assert!(message.contains("in check at instruction 4"));
+    assert!(message.contains("called by main at instruction 1"));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{SharedOutput, load};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, CpuStatus, ExitReason, InstructionArgument, Register,
+};
+
+#[test]
+fn a_program_built_through_the_public_api_runs_to_completion() {
+    // Sums 1 to 5 in the first global slot
+    let output = SharedOutput::default();
+    let mut cpu = Cpu16::with_output(0, Box::new(output.clone()));
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(5),
+            InstructionArgument::Register(Register::A),
+        ),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Add(
+            InstructionArgument::Global(0),
+            InstructionArgument::Register(Register::A),
+        ),
+        CpuInstruction::Mov(
+            InstructionArgument::Register(Register::Res),
+            InstructionArgument::Global(0),
+        ),
+        CpuInstruction::Loop(InstructionArgument::Register(Register::A), "loop".into()),
+        CpuInstruction::Print(InstructionArgument::Global(0)),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+    assert!(matches!(cpu.get_status(), CpuStatus::NotStarted));
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(report.exit, ExitReason::Finished));
+    assert_eq!(report.registers.res, 15);
+    assert_eq!(cpu.get_register_by_name("a").unwrap(), 0);
+    assert_eq!(cpu.memory().read_global(0).unwrap(), 15);
+    assert_eq!(output.text(), "15\n");
+}
+
+#[test]
+fn host_functions_are_called_by_name() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::CallArgs("double".into(), vec![InstructionArgument::SignedValue(-21)]),
+        CpuInstruction::Print(InstructionArgument::Register(Register::Res)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.register_host_fn("double", |context| {
+        Some(context.arguments[0].wrapping_mul(2))
+    });
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), format!("{}\n", (-42i16) as u16));
+}
+
+#[test]
+fn errors_stop_the_cpu() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Div(InstructionArgument::Value(1), InstructionArgument::Value(0)),
+        CpuInstruction::Ret(),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error.inner(), CpuError::DivisionByZero));
+    assert!(matches!(cpu.get_status(), CpuStatus::Failed(_)));
+}
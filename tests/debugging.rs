@@ -0,0 +1,352 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::{load, load_main, reg};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, CpuStatus, ExitReason, InstructionArgument, Register,
+};
+
+#[test]
+fn the_host_can_inspect_and_change_the_cpu_while_it_is_paused() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(0)),
+        CpuInstruction::Brk(),
+        CpuInstruction::Print(reg(Register::A)),
+    ]);
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(report.exit, ExitReason::Paused { address: 3 }));
+    assert!(matches!(cpu.get_status(), CpuStatus::Paused));
+    assert_eq!(cpu.memory().read_data(0).unwrap(), 5);
+    assert_eq!(output.text(), "");
+
+    cpu.set_register("a", 42).unwrap();
+    let report = cpu.resume().unwrap();
+    assert!(matches!(report.exit, ExitReason::Finished));
+    assert_eq!(output.text(), "42\n");
+}
+
+/** Runs the program until its BRK, and dumps the memory at that point */
+fn dump_at_break(cpu: &mut Cpu16) -> String {
+    cpu.execute().unwrap();
+    let mut dump = vec![];
+    cpu.dump_memory(&mut dump).unwrap();
+
+    String::from_utf8(dump).unwrap()
+}
+
+#[test]
+fn the_memory_dump_shows_empty_sub_stacks() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Brk(),
+        CpuInstruction::Ret(),
+    ]);
+
+    assert_eq!(
+        dump_at_break(&mut cpu),
+        "Sub stack 0 (return address 1, 0 slots)\n  (empty)\n\
+         Sub stack 1 (return address 6, 0 slots)\n  (empty)\n\
+         Global memory (0 slots)\n  (empty)\n"
+    );
+}
+
+#[test]
+fn the_memory_dump_truncates_long_sub_stacks() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Brk()]);
+    cpu.preload_frame(&(1..=20).collect::<Vec<_>>());
+    cpu.preload_global(&[0xABCD]).unwrap();
+    cpu.set_max_dump_rows(2);
+
+    assert_eq!(
+        dump_at_break(&mut cpu),
+        "Sub stack 0 (return address 3, 20 slots)\n\
+         \x20 0000: 0001 0002 0003 0004 0005 0006 0007 0008\n\
+         \x20 0008: 0009 000a 000b 000c 000d 000e 000f 0010\n\
+         \x20 ... 1 more rows\n\
+         Global memory (1 slots)\n\
+         \x20 0000: abcd\n"
+    );
+}
+
+#[test]
+fn the_memory_dump_shows_every_row_within_the_limit() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Brk()]);
+    cpu.preload_frame(&(1..=9).collect::<Vec<_>>());
+
+    assert_eq!(
+        dump_at_break(&mut cpu),
+        "Sub stack 0 (return address 3, 9 slots)\n\
+         \x20 0000: 0001 0002 0003 0004 0005 0006 0007 0008\n\
+         \x20 0008: 0009\n\
+         Global memory (0 slots)\n  (empty)\n"
+    );
+}
+
+#[test]
+fn restoring_a_snapshot_replays_the_rest_of_the_program_identically() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(10), reg(Register::A)),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Rand(InstructionArgument::Value(1000)),
+        CpuInstruction::Add(reg(Register::Res), InstructionArgument::Global(0)),
+        CpuInstruction::Mov(reg(Register::Res), InstructionArgument::Global(0)),
+        CpuInstruction::Push(reg(Register::Res)),
+        CpuInstruction::Loop(reg(Register::A), "loop".into()),
+        CpuInstruction::Print(InstructionArgument::Global(0)),
+    ]);
+    cpu.set_rng_seed(7);
+    cpu.start().unwrap();
+    for _ in 0..20 {
+        assert!(cpu.step());
+    }
+    let halfway = cpu.snapshot();
+
+    while cpu.step() {}
+    let first_run = cpu.snapshot();
+    let first_output = output.text();
+
+    cpu.restore(&halfway);
+    while cpu.step() {}
+    let second_run = cpu.snapshot();
+
+    assert_ne!(halfway.memory(), first_run.memory());
+    assert_eq!(first_run.registers(), second_run.registers());
+    assert_eq!(first_run.memory(), second_run.memory());
+    assert_eq!(output.text(), first_output.repeat(2));
+}
+
+/** The watcher calls, as (watcher, old value, new value, instruction address) */
+type WatchLog = Rc<RefCell<Vec<(&'static str, u16, u16, u16)>>>;
+
+fn logging(log: &WatchLog, watcher: &'static str) -> impl FnMut(u16, u16, u16) + 'static {
+    let log = log.clone();
+    move |old_value, new_value, address| {
+        log.borrow_mut()
+            .push((watcher, old_value, new_value, address))
+    }
+}
+
+#[test]
+fn watchers_see_every_write_of_a_counting_program_in_order() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(3), InstructionArgument::Stack(0)),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Add(
+            InstructionArgument::Global(0),
+            InstructionArgument::Value(10),
+        ),
+        CpuInstruction::Mov(reg(Register::Res), InstructionArgument::Global(0)),
+        CpuInstruction::Dec(InstructionArgument::Stack(0)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+        CpuInstruction::Push(InstructionArgument::Value(7)),
+    ]);
+    let log = WatchLog::default();
+    cpu.watch_register("res", logging(&log, "res")).unwrap();
+    cpu.watch_memory(0, logging(&log, "stack 0"));
+    cpu.watch_memory(1, logging(&log, "stack 1"));
+    cpu.watch_global(0, logging(&log, "global"));
+    cpu.watch_global(0, logging(&log, "global again"));
+
+    cpu.execute().unwrap();
+    let expected = [
+        ("stack 0", 0, 3, 1),
+        ("res", 0, 10, 3),
+        ("global", 0, 10, 4),
+        ("global again", 0, 10, 4),
+        ("stack 0", 3, 2, 5),
+        ("res", 10, 2, 5),
+        ("res", 2, 20, 3),
+        ("global", 10, 20, 4),
+        ("global again", 10, 20, 4),
+        ("stack 0", 2, 1, 5),
+        ("res", 20, 1, 5),
+        ("res", 1, 30, 3),
+        ("global", 20, 30, 4),
+        ("global again", 20, 30, 4),
+        ("stack 0", 1, 0, 5),
+        ("res", 30, 0, 5),
+        ("stack 1", 0, 7, 7),
+    ];
+    assert_eq!(*log.borrow(), expected);
+}
+
+#[test]
+fn watchers_see_the_writes_of_host_functions_and_callers() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Call("host".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(4),
+            InstructionArgument::CallerStack(0),
+        ),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.register_host_fn("host", |context| {
+        context.registers.general[Register::A as usize] = 8;
+        context.memory.write_global(2, 9).unwrap();
+        Some(1)
+    });
+    let log = WatchLog::default();
+    cpu.watch_register("a", logging(&log, "a")).unwrap();
+    cpu.watch_register("res", logging(&log, "res")).unwrap();
+    cpu.watch_memory(0, logging(&log, "stack 0"));
+    cpu.watch_global(2, logging(&log, "global 2"));
+
+    cpu.execute().unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [
+            ("stack 0", 0, 4, 5),
+            ("a", 0, 8, 2),
+            ("global 2", 0, 9, 2),
+            ("res", 0, 1, 2),
+        ]
+    );
+}
+
+#[test]
+fn watchers_see_the_result_of_retval_at_the_retval() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::RetVal(InstructionArgument::Value(6)),
+    ]);
+    let log = WatchLog::default();
+    cpu.watch_register("res", logging(&log, "res")).unwrap();
+
+    cpu.execute().unwrap();
+    assert_eq!(*log.borrow(), [("res", 0, 6, 4)]);
+}
+
+#[test]
+fn a_failure_three_calls_deep_is_backtraced_through_every_caller() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("outer".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("outer".into(), 0),
+        CpuInstruction::Nop(),
+        CpuInstruction::Call("middle".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("middle".into(), 0),
+        CpuInstruction::Call("inner".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("inner".into(), 0),
+        CpuInstruction::Div(InstructionArgument::Value(1), InstructionArgument::Value(0)),
+        CpuInstruction::Ret(),
+    ]);
+    let error = cpu.execute().unwrap_err();
+
+    let CpuError::Backtraced { backtrace, .. } = &error else {
+        panic!("Expected a backtrace, got {error:?}");
+    };
+    assert_eq!(backtrace.address, 11);
+    assert_eq!(backtrace.function.as_deref(), Some("inner"));
+    assert!(backtrace.instruction.starts_with("Div("));
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "Division by zero\n  in inner at instruction 11: {}\n  called by middle at instruction 8\n  called by outer at instruction 5\n  called by main at instruction 1",
+            backtrace.instruction
+        )
+    );
+}
+
+#[test]
+fn the_report_of_the_counting_program() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(3)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+    ]);
+    let report = cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "1\n2\n3\n");
+    assert!(matches!(report.exit, ExitReason::Finished));
+    // Three iterations of five instructions, the call to "main" and its RET
+    assert_eq!(report.instructions, 3 * 5 + 2);
+    assert_eq!(report.cycles, report.instructions);
+    assert_eq!(report.registers.general[Register::A as usize], 3);
+    assert_eq!(report.registers.res, 0);
+    assert!(report.duration > std::time::Duration::ZERO);
+    assert!(
+        report
+            .to_string()
+            .ends_with("seconds (17 instructions, 17 cycles)")
+    );
+}
+
+#[test]
+fn the_report_tells_how_the_program_stopped() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Nop(),
+        CpuInstruction::Exit(),
+        CpuInstruction::Nop(),
+    ]);
+    let report = cpu.execute().unwrap();
+    assert!(matches!(report.exit, ExitReason::Exited));
+    assert_eq!(report.instructions, 3);
+
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Halt(InstructionArgument::Value(4)),
+        CpuInstruction::Nop(),
+    ]);
+    let report = cpu.execute().unwrap();
+    assert!(matches!(
+        report.exit,
+        ExitReason::Halted {
+            code: 4,
+            address: 1
+        }
+    ));
+    assert!(
+        report
+            .to_string()
+            .starts_with("CPU halted with code 4 at instruction 1")
+    );
+}
+
+#[test]
+fn a_program_runs_only_once() {
+    let (mut cpu, output) = load_main(vec![CpuInstruction::Print(InstructionArgument::Value(1))]);
+    cpu.execute().unwrap();
+    let cycles = cpu.get_cycles();
+
+    assert!(matches!(
+        cpu.execute().unwrap_err(),
+        CpuError::AlreadyStarted
+    ));
+    assert!(matches!(cpu.start().unwrap_err(), CpuError::AlreadyStarted));
+    assert_eq!(cpu.get_cycles(), cycles);
+    assert_eq!(output.text(), "1\n");
+}
+
+#[test]
+fn a_paused_program_continues_with_resume_only() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Brk(),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+    ]);
+    cpu.execute().unwrap();
+
+    assert!(matches!(
+        cpu.execute().unwrap_err(),
+        CpuError::AlreadyStarted
+    ));
+    assert!(matches!(cpu.get_status(), CpuStatus::Paused));
+    cpu.resume().unwrap();
+    assert_eq!(output.text(), "1\n");
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::sync::mpsc;
+
+use common::{load_main, reg};
+use emulated_cpu::cpu::{CpuError, CpuInstruction, InstructionArgument, Register};
+use emulated_cpu::device::{CounterDevice, FrameBufferDevice, SerialDevice};
+
+#[test]
+fn a_program_reads_and_sets_a_counter_device() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Print(InstructionArgument::Stack(10)),
+        CpuInstruction::Print(InstructionArgument::Stack(10)),
+        CpuInstruction::Print(InstructionArgument::Stack(11)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(100),
+            InstructionArgument::Stack(10),
+        ),
+        CpuInstruction::Print(InstructionArgument::Stack(10)),
+        CpuInstruction::Print(InstructionArgument::Stack(10)),
+    ]);
+    cpu.map_device(10, 2, Box::new(CounterDevice::default()))
+        .unwrap();
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "0\n1\n2\n100\n101\n");
+}
+
+#[test]
+fn devices_cannot_overlap() {
+    let (mut cpu, _) = load_main(vec![]);
+    cpu.map_device(10, 4, Box::new(CounterDevice::default()))
+        .unwrap();
+
+    let error = cpu
+        .map_device(12, 4, Box::new(CounterDevice::default()))
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CpuError::OverlappingDevice {
+            start: 12,
+            length: 4
+        }
+    ));
+    let error = cpu
+        .map_device(8, 3, Box::new(CounterDevice::default()))
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CpuError::OverlappingDevice {
+            start: 8,
+            length: 3
+        }
+    ));
+    // Ranges touching the mapped one are free
+    cpu.map_device(14, 2, Box::new(CounterDevice::default()))
+        .unwrap();
+    cpu.map_device(8, 2, Box::new(CounterDevice::default()))
+        .unwrap();
+}
+
+#[test]
+fn writing_a_device_leaves_the_stack_slot_under_it_alone() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Brk(),
+    ]);
+    cpu.map_device(0, 1, Box::new(CounterDevice::default()))
+        .unwrap();
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "5\n");
+    assert_eq!(cpu.memory().read_data(0).unwrap(), 0);
+}
+
+#[test]
+fn a_program_echoes_what_the_host_sends_to_the_serial_console() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Label("echo".into()),
+        CpuInstruction::Mov(InstructionArgument::Stack(21), reg(Register::A)),
+        CpuInstruction::Jnz(InstructionArgument::Stack(22), "done".into()),
+        CpuInstruction::Mov(reg(Register::A), InstructionArgument::Stack(20)),
+        CpuInstruction::Jz(InstructionArgument::Stack(22), "echo".into()),
+        CpuInstruction::Label("done".into()),
+    ]);
+    let (to_host, from_cpu) = mpsc::channel();
+    let (to_cpu, from_host) = mpsc::channel();
+    cpu.map_device(20, 3, Box::new(SerialDevice::new(to_host, from_host)))
+        .unwrap();
+    for byte in b"ok" {
+        to_cpu.send(*byte).unwrap();
+    }
+    cpu.execute().unwrap();
+
+    assert_eq!(from_cpu.try_iter().collect::<Vec<_>>(), b"ok");
+}
+
+#[test]
+fn the_frame_buffer_renders_what_the_program_wrote() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(
+            InstructionArgument::Value(b'H'.into()),
+            InstructionArgument::Stack(1000),
+        ),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(b'I'.into()),
+            InstructionArgument::Stack(1001),
+        ),
+        // The third column of the second row
+        CpuInstruction::Mov(
+            InstructionArgument::Value(b'!'.into()),
+            InstructionArgument::Stack(1082),
+        ),
+    ]);
+    let screen = FrameBufferDevice::new();
+    let size = FrameBufferDevice::WIDTH * FrameBufferDevice::HEIGHT;
+    cpu.map_device(1000, size, Box::new(screen.clone()))
+        .unwrap();
+    cpu.execute().unwrap();
+
+    let rendered = screen.render();
+    assert_eq!(
+        rendered.split('\n').count(),
+        FrameBufferDevice::HEIGHT as usize
+    );
+    assert_eq!(rendered.trim_end(), "HI\n  !");
+}
@@ -0,0 +1,271 @@
+/*! Every error a program can stop the CPU with, or be rejected with when it is loaded.
+The errors raised by the rest of the host API, such as overlapping devices, are tested along with that API */
+mod common;
+
+use std::collections::VecDeque;
+
+use common::{fail_main, load, load_main, reg};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, CpuState, FloatRegister, InstructionArgument, MemoryError,
+    Register,
+};
+
+/** Runs "main" made of the instructions once the host set the CPU up, it must fail */
+fn fail_main_with(setup: impl FnOnce(&mut Cpu16), body: Vec<CpuInstruction>) -> CpuError {
+    let (mut cpu, _) = load_main(body);
+    setup(&mut cpu);
+
+    cpu.execute().unwrap_err().inner().clone()
+}
+
+/** An output refusing every write */
+struct BrokenOutput;
+impl std::io::Write for BrokenOutput {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/** Loads the instructions into a CPU, they must be rejected */
+fn reject(setup: impl FnOnce(&mut Cpu16), instructions: &[CpuInstruction]) -> CpuError {
+    let mut cpu = Cpu16::new(0);
+    setup(&mut cpu);
+
+    cpu.append_instructions(instructions).unwrap_err()
+}
+
+#[test]
+fn load_errors() {
+    let error = reject(
+        |_| {},
+        &[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::GotoLabel("nowhere".into()),
+        ],
+    );
+    assert!(matches!(error, CpuError::UnknownLabel(label) if label == "nowhere"));
+
+    let error = reject(
+        |_| {},
+        &[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::Switch(InstructionArgument::Value(0), vec![5]),
+        ],
+    );
+    assert!(matches!(error, CpuError::JumpOutOfBounds(5)));
+
+    let error = reject(
+        |cpu| cpu.protect_res = true,
+        &[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::Res)),
+        ],
+    );
+    assert!(matches!(error, CpuError::ProtectedResult));
+
+    let error = reject(
+        |_| {},
+        &[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::Fn("main".into(), 0),
+        ],
+    );
+    assert!(matches!(
+        error,
+        CpuError::DuplicateFunction {
+            first_address: 0,
+            second_address: 1,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn arithmetic_errors() {
+    let error = fail_main(vec![CpuInstruction::Div(
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(0),
+    )]);
+    assert!(matches!(error, CpuError::DivisionByZero));
+
+    let error = fail_main_with(
+        |cpu| cpu.trap_on_overflow = true,
+        vec![CpuInstruction::Add(
+            InstructionArgument::Value(0xFFFF),
+            InstructionArgument::Value(1),
+        )],
+    );
+    assert!(matches!(
+        error,
+        CpuError::Overflow {
+            first: 0xFFFF,
+            second: 1
+        }
+    ));
+
+    let error = fail_main(vec![CpuInstruction::Assert(
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(2),
+    )]);
+    assert!(matches!(
+        error,
+        CpuError::AssertionFailed {
+            actual: 1,
+            expected: 2
+        }
+    ));
+}
+
+#[test]
+fn argument_errors() {
+    let error = fail_main(vec![CpuInstruction::Mov(
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(2),
+    )]);
+    assert!(matches!(error, CpuError::InvalidDestination(2)));
+
+    let error = fail_main(vec![CpuInstruction::Mov(
+        InstructionArgument::Value(1),
+        reg(Register::Cycles),
+    )]);
+    assert!(matches!(
+        error,
+        CpuError::ReadOnlyRegister(Register::Cycles)
+    ));
+
+    let error = fail_main(vec![CpuInstruction::FAdd(
+        reg(Register::A),
+        InstructionArgument::FloatRegister(FloatRegister::F0),
+    )]);
+    assert!(matches!(error, CpuError::NotAFloatRegister));
+
+    let error = fail_main(vec![CpuInstruction::Add(
+        InstructionArgument::FloatRegister(FloatRegister::F0),
+        InstructionArgument::Value(1),
+    )]);
+    assert!(matches!(error, CpuError::UnexpectedFloatRegister));
+
+    let (mut cpu, _): (CpuState<u8>, _) = load(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Print(InstructionArgument::SignedValue(-200)),
+        CpuInstruction::Ret(),
+    ]);
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::SignedValueOutOfRange(-200)
+    ));
+}
+
+#[test]
+fn control_flow_errors() {
+    let error = fail_main(vec![CpuInstruction::Jump(100)]);
+    assert!(matches!(error, CpuError::JumpOutOfBounds(101)));
+
+    let error = fail_main(vec![CpuInstruction::Call("nowhere".into())]);
+    assert!(matches!(error, CpuError::UnknownFunction(fn_name) if fn_name == "nowhere"));
+
+    let error = fail_main(vec![CpuInstruction::CallIndirect(
+        InstructionArgument::Value(1),
+    )]);
+    assert!(matches!(error, CpuError::NotAFunction(1)));
+
+    let error = fail_main(vec![CpuInstruction::Call("main".into())]);
+    assert!(matches!(
+        error,
+        CpuError::StackOverflow { depth: 1024, fn_name } if fn_name == "main"
+    ));
+}
+
+#[test]
+fn memory_errors() {
+    let error = fail_main(vec![CpuInstruction::Pop(reg(Register::A))]);
+    assert!(matches!(error, CpuError::StackUnderflow));
+
+    let error = fail_main(vec![CpuInstruction::MemSet(
+        InstructionArgument::Value(0),
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(1),
+    )]);
+    assert!(matches!(error, CpuError::NotAMemoryLocation));
+
+    let error = fail_main(vec![CpuInstruction::MemSet(
+        InstructionArgument::Global(0xFFFF),
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(2),
+    )]);
+    assert!(matches!(
+        error,
+        CpuError::RangeOutOfBounds {
+            address: 0xFFFF,
+            length: 2
+        }
+    ));
+
+    let error = fail_main(vec![CpuInstruction::Print(InstructionArgument::Data(0))]);
+    assert!(matches!(
+        error,
+        CpuError::DataOutOfBounds {
+            address: 0,
+            size: 0
+        }
+    ));
+
+    let error = fail_main(vec![CpuInstruction::Mov(
+        InstructionArgument::Value(1),
+        InstructionArgument::Data(0),
+    )]);
+    assert!(matches!(error, CpuError::WriteToReadOnly(0)));
+
+    let error = fail_main(vec![CpuInstruction::Free(InstructionArgument::Value(5))]);
+    assert!(matches!(
+        error,
+        CpuError::Memory(MemoryError::InvalidFree(5))
+    ));
+}
+
+#[test]
+fn host_service_errors() {
+    let error = fail_main(vec![CpuInstruction::Syscall(InstructionArgument::Value(1))]);
+    assert!(matches!(error, CpuError::UnknownSyscall(1)));
+
+    let error = fail_main(vec![CpuInstruction::Int(InstructionArgument::Value(2))]);
+    assert!(matches!(error, CpuError::UnhandledInterrupt(2)));
+
+    let error = fail_main(vec![CpuInstruction::Custom(3, vec![])]);
+    assert!(matches!(error, CpuError::IllegalInstruction(3)));
+}
+
+#[test]
+fn io_errors() {
+    let error = fail_main_with(
+        |cpu| cpu.set_output(Box::new(BrokenOutput)),
+        vec![CpuInstruction::Print(InstructionArgument::Value(1))],
+    );
+    assert!(matches!(error, CpuError::Io(error) if error.kind() == std::io::ErrorKind::BrokenPipe));
+
+    let error = fail_main_with(
+        |cpu| cpu.set_input(Box::new(VecDeque::<u64>::new())),
+        vec![CpuInstruction::Read(reg(Register::A))],
+    );
+    assert!(matches!(error, CpuError::NoInput));
+}
+
+#[test]
+fn the_error_stopping_the_cpu_tells_where_it_happened() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Nop(),
+        CpuInstruction::Pop(reg(Register::A)),
+    ]);
+    let error = cpu.execute().unwrap_err();
+
+    let CpuError::Backtraced { error, backtrace } = &error else {
+        panic!("Expected a backtrace, got {error:?}");
+    };
+    assert!(matches!(**error, CpuError::StackUnderflow));
+    assert_eq!(backtrace.address, 2);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{reg, run_main};
+use emulated_cpu::cpu::{
+    Cpu16, CpuInstruction, FLAG_CARRY, FloatRegister, InstructionArgument, Register,
+};
+
+fn freg(register: FloatRegister) -> InstructionArgument {
+    InstructionArgument::FloatRegister(register)
+}
+
+/** Converts the value into the float register */
+fn load_float(value: u16, register: FloatRegister) -> [CpuInstruction; 2] {
+    [
+        CpuInstruction::IntToFloat(InstructionArgument::Value(value)),
+        CpuInstruction::Mov(freg(FloatRegister::FRes), freg(register)),
+    ]
+}
+
+/** Runs "main" applying the instructions to the floats, and returns the CPU */
+fn with_floats(values: &[u16], instructions: Vec<CpuInstruction>) -> Cpu16 {
+    let registers = [FloatRegister::F0, FloatRegister::F1, FloatRegister::F2];
+    let mut body: Vec<_> = values
+        .iter()
+        .zip(registers)
+        .flat_map(|(&value, register)| load_float(value, register))
+        .collect();
+    body.extend(instructions);
+
+    run_main(body).0
+}
+
+#[test]
+fn the_average_of_three_values() {
+    let cpu = with_floats(
+        &[1, 2, 4],
+        vec![
+            CpuInstruction::FAdd(freg(FloatRegister::F0), freg(FloatRegister::F1)),
+            CpuInstruction::FAdd(freg(FloatRegister::FRes), freg(FloatRegister::F2)),
+            CpuInstruction::Mov(freg(FloatRegister::FRes), freg(FloatRegister::F0)),
+            CpuInstruction::IntToFloat(InstructionArgument::Value(3)),
+            CpuInstruction::FDiv(freg(FloatRegister::F0), freg(FloatRegister::FRes)),
+            CpuInstruction::FloatToInt(freg(FloatRegister::FRes)),
+        ],
+    );
+
+    assert_eq!(cpu.get_float_register(FloatRegister::FRes), 7.0 / 3.0);
+    // Rounded towards zero
+    assert_eq!(cpu.get_register(Register::Res), 2);
+}
+
+#[test]
+fn integers_round_trip_through_floats() {
+    for value in [0, 1, 1234, u16::MAX] {
+        let cpu = with_floats(
+            &[value],
+            vec![CpuInstruction::FloatToInt(freg(FloatRegister::F0))],
+        );
+
+        assert_eq!(cpu.get_float_register(FloatRegister::F0), value as f32);
+        assert_eq!(cpu.get_register(Register::Res), value);
+        assert_eq!(cpu.get_register(Register::Flags) & FLAG_CARRY, 0);
+    }
+}
+
+#[test]
+fn float_to_int_clamps_out_of_range_floats_and_nan() {
+    let cpu = with_floats(
+        &[1, 0],
+        vec![
+            // 1 / 0 is infinite, 0 / 0 is NaN
+            CpuInstruction::FDiv(freg(FloatRegister::F0), freg(FloatRegister::F1)),
+            CpuInstruction::FloatToInt(freg(FloatRegister::FRes)),
+            CpuInstruction::Mov(reg(Register::Res), reg(Register::A)),
+            CpuInstruction::Mov(reg(Register::Flags), reg(Register::B)),
+            CpuInstruction::FDiv(freg(FloatRegister::F1), freg(FloatRegister::F1)),
+            CpuInstruction::FloatToInt(freg(FloatRegister::FRes)),
+        ],
+    );
+
+    assert_eq!(cpu.get_register(Register::A), u16::MAX);
+    assert_eq!(cpu.get_register(Register::B) & FLAG_CARRY, FLAG_CARRY);
+    assert!(cpu.get_float_register(FloatRegister::FRes).is_nan());
+    assert_eq!(cpu.get_register(Register::Res), 0);
+    assert_eq!(cpu.get_register(Register::Flags) & FLAG_CARRY, FLAG_CARRY);
+}
@@ -0,0 +1,494 @@
+mod common;
+
+use common::{load, load_main, reg};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, InstructionArgument, MemoryError, Register,
+};
+
+#[test]
+fn a_program_can_be_built_from_strings_made_at_runtime() {
+    let source = String::from("main add_two again");
+    let names: Vec<String> = source.split_whitespace().map(String::from).collect();
+    let [main, add_two, again] = [0, 1, 2].map(|index| names[index].clone());
+    drop(names);
+
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn(main.into(), 0),
+        CpuInstruction::Label(again.clone().into()),
+        CpuInstruction::Call(add_two.clone().into()),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(6)),
+        CpuInstruction::Jnz(reg(Register::Res), again.into()),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn(add_two.into(), 0),
+        CpuInstruction::Add(reg(Register::A), InstructionArgument::Value(2)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::A)),
+        CpuInstruction::Ret(),
+    ]);
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "6\n");
+}
+
+#[test]
+fn a_function_declared_twice_is_rejected_with_both_addresses() {
+    let mut cpu = Cpu16::new(0);
+    cpu.append_instructions(&[CpuInstruction::Fn("main".into(), 0), CpuInstruction::Ret()])
+        .unwrap();
+
+    let error = cpu
+        .append_instructions(&[CpuInstruction::Fn("main".into(), 0), CpuInstruction::Ret()])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CpuError::DuplicateFunction {
+            ref name,
+            first_address: 0,
+            second_address: 2,
+        } if name == "main"
+    ));
+}
+
+#[test]
+fn a_rejected_duplicate_declares_none_of_the_functions() {
+    let (mut cpu, output) = load::<u16>(&[]);
+
+    let error = cpu
+        .append_instructions(&[
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::Ret(),
+            CpuInstruction::Fn("main".into(), 0),
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CpuError::DuplicateFunction {
+            first_address: 0,
+            second_address: 2,
+            ..
+        }
+    ));
+
+    // "main" must now be declared by the instructions actually loaded
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("g".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("g".into()),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "1\n");
+}
+
+#[test]
+fn distinct_functions_can_be_appended_separately() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("first".into()),
+        CpuInstruction::Call("second".into()),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("first".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("second".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "1\n2\n");
+}
+
+#[test]
+fn the_call_stack_lists_the_callers_from_the_innermost_function() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("outer".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("outer".into(), 0),
+        CpuInstruction::Call("inner".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("inner".into(), 0),
+        CpuInstruction::Brk(),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    let frames = cpu.call_stack();
+    let callers: Vec<_> = frames.iter().map(|frame| frame.caller.as_deref()).collect();
+    let return_addresses: Vec<_> = frames.iter().map(|frame| frame.return_address).collect();
+    assert_eq!(callers, [Some("outer"), Some("main"), None]);
+    assert_eq!(return_addresses, [4, 1, 9]);
+}
+
+/** "main" calls "f", which calls a host function making it return past the next PRINT */
+fn load_tampered_return(guard_frames: bool) -> (Cpu16, common::SharedOutput) {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Call("tamper".into()),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.guard_frames = guard_frames;
+    cpu.register_host_fn("tamper", |context| {
+        context.memory.get_current_sub_stack_mut().return_address = 2;
+        None
+    });
+
+    (cpu, output)
+}
+
+#[test]
+fn a_return_address_changed_by_the_host_is_followed_without_frame_guards() {
+    let (mut cpu, output) = load_tampered_return(false);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "2\n");
+}
+
+#[test]
+fn a_return_address_changed_by_the_host_is_detected_with_frame_guards() {
+    let (mut cpu, output) = load_tampered_return(true);
+    let error = cpu.execute().unwrap_err();
+
+    assert!(matches!(
+        error.inner(),
+        CpuError::FrameCorrupted { depth: 2, fn_name } if fn_name == "f"
+    ));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn call_if_returns_after_itself_whether_taken_or_not() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Mod(reg(Register::A), InstructionArgument::Value(2)),
+        CpuInstruction::CallIf(reg(Register::Res), "odd".into()),
+        CpuInstruction::Print(InstructionArgument::Value(0)),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(4)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("odd".into(), 0),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "0\n1\n0\n0\n3\n0\n");
+}
+
+#[test]
+fn call_args_fills_the_first_slots_of_the_callee() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(6), InstructionArgument::Stack(0)),
+        CpuInstruction::Mov(InstructionArgument::Value(7), InstructionArgument::Stack(1)),
+        CpuInstruction::CallArgs(
+            "f".into(),
+            vec![InstructionArgument::Stack(1), InstructionArgument::Stack(0)],
+        ),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(1)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(1)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(99),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "7\n6\n6\n7\n");
+}
+
+#[test]
+fn ret_val_hands_a_slot_or_a_register_back_through_nested_calls() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(0)),
+        CpuInstruction::Call("outer".into()),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("outer".into(), 0),
+        CpuInstruction::Call("inner".into()),
+        CpuInstruction::Add(reg(Register::Res), InstructionArgument::Value(22)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+        CpuInstruction::Mov(InstructionArgument::Value(0), reg(Register::Res)),
+        CpuInstruction::RetVal(reg(Register::B)),
+        CpuInstruction::Fn("inner".into(), 0),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(20),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::RetVal(InstructionArgument::Stack(0)),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "42\n5\n");
+}
+
+#[test]
+fn call_indirect_dispatches_through_a_table_in_the_stack() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::LoadFnAddr("double".into()),
+        CpuInstruction::Mov(reg(Register::Res), InstructionArgument::Stack(0)),
+        CpuInstruction::LoadFnAddr("square".into()),
+        CpuInstruction::Mov(reg(Register::Res), InstructionArgument::Stack(1)),
+        CpuInstruction::Mov(InstructionArgument::Value(5), reg(Register::A)),
+        CpuInstruction::CallIndirect(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::CallIndirect(InstructionArgument::Stack(1)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("double".into(), 0),
+        CpuInstruction::Add(reg(Register::A), reg(Register::A)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("square".into(), 0),
+        CpuInstruction::Mul(reg(Register::A), reg(Register::A)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "10\n25\n");
+}
+
+#[test]
+fn calling_an_address_without_a_function_stops_the_cpu() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::CallIndirect(InstructionArgument::Value(2)),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error.inner(), CpuError::NotAFunction(2)));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn tail_recursion_runs_in_a_single_frame() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(10_000), reg(Register::A)),
+        CpuInstruction::Call("countdown".into()),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Print(reg(Register::B)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("countdown".into(), 0),
+        // "b" keeps the deepest call depth seen
+        CpuInstruction::Max(reg(Register::B), reg(Register::Sp)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+        CpuInstruction::Dec(reg(Register::A)),
+        CpuInstruction::IfThen(
+            reg(Register::Res),
+            Box::new(CpuInstruction::TailCall("countdown".into())),
+        ),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "0\n2\n");
+}
+
+/** Runs "main" with a sub stack of 3 slots declared, returning what it printed or how it failed */
+fn in_a_sized_frame(body: Vec<CpuInstruction>) -> Result<String, CpuError> {
+    let mut instructions = vec![CpuInstruction::Fn("main".into(), 3)];
+    instructions.extend(body);
+    instructions.push(CpuInstruction::Ret());
+    let (mut cpu, output) = load(&instructions);
+
+    match cpu.execute() {
+        Ok(_) => Ok(output.text()),
+        Err(error) => Err(error.inner().clone()),
+    }
+}
+
+#[test]
+fn a_declared_frame_starts_with_its_slots_filled_with_0() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 3),
+        CpuInstruction::Brk(),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(cpu.call_stack()[0].data_length, 3);
+    assert_eq!(cpu.memory().read_data(2).unwrap(), 0);
+}
+
+#[test]
+fn the_slots_below_the_declared_size_can_be_read_and_written() {
+    let output = in_a_sized_frame(vec![
+        CpuInstruction::Print(InstructionArgument::Stack(2)),
+        CpuInstruction::Mov(InstructionArgument::Value(4), InstructionArgument::Stack(0)),
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(2)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(2)),
+    ]);
+
+    assert_eq!(output.unwrap(), "0\n4\n5\n");
+}
+
+#[test]
+fn the_slots_at_and_above_the_declared_size_are_out_of_bounds() {
+    for address in [3, 10] {
+        let read = in_a_sized_frame(vec![CpuInstruction::Print(InstructionArgument::Stack(
+            address,
+        ))]);
+        let write = in_a_sized_frame(vec![CpuInstruction::Mov(
+            InstructionArgument::Value(1),
+            InstructionArgument::Stack(address),
+        )]);
+
+        for result in [read, write] {
+            assert!(matches!(
+                result,
+                Err(CpuError::Memory(MemoryError::OutOfBounds { address: found, size: 3 }))
+                    if found == u64::from(address)
+            ));
+        }
+    }
+}
+
+#[test]
+fn calling_an_unknown_function_names_it_in_the_error() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("mian".into()),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error.inner(), CpuError::UnknownFunction(fn_name) if fn_name == "mian"));
+    assert!(error.to_string().starts_with("Function \"mian\" not found"));
+    assert!(error.to_string().contains("in main at instruction 1"));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn unbounded_recursion_ends_in_a_stack_overflow() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("forever".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("forever".into(), 0),
+        CpuInstruction::Call("forever".into()),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.max_call_depth = 100;
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::StackOverflow { depth: 100, fn_name } if fn_name == "forever"
+    ));
+    assert_eq!(cpu.memory().get_depth(), 100);
+}
+
+#[test]
+fn main_starts_with_the_preloaded_frame() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Add(
+        InstructionArgument::Stack(0),
+        InstructionArgument::Stack(1),
+    )]);
+    cpu.preload_frame(&[3, 4]);
+    let report = cpu.execute().unwrap();
+
+    assert_eq!(report.registers.res, 7);
+}
+
+#[test]
+fn the_preloaded_frame_only_goes_to_main() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("callee".into()),
+        CpuInstruction::Print(InstructionArgument::Stack(1)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("callee".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Stack(1)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.preload_frame(&[3, 4]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "0\n4\n");
+}
+
+#[test]
+fn ten_thousand_nested_calls_keep_their_frames_in_order() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(10000), reg(Register::A)),
+        CpuInstruction::Mov(reg(Register::A), InstructionArgument::Stack(0)),
+        CpuInstruction::Call("down".into()),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(reg(Register::B)),
+        CpuInstruction::Ret(),
+        // Every frame holds one less than the frame of its caller
+        CpuInstruction::Fn("down".into(), 0),
+        CpuInstruction::Sub(reg(Register::A), InstructionArgument::Value(1)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::A)),
+        CpuInstruction::Mov(reg(Register::A), InstructionArgument::Stack(0)),
+        CpuInstruction::Add(InstructionArgument::Stack(0), InstructionArgument::Value(1)),
+        CpuInstruction::Assert(InstructionArgument::CallerStack(0), reg(Register::Res)),
+        CpuInstruction::Inc(reg(Register::B)),
+        CpuInstruction::CallIf(reg(Register::A), "down".into()),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.max_call_depth = 10001;
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "10000\n10000\n");
+}
+
+#[test]
+fn the_caller_stack_is_the_frame_of_the_direct_caller() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(0)),
+        CpuInstruction::Call("outer".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("outer".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(2), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::CallerStack(0)),
+        CpuInstruction::Call("inner".into()),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("inner".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(3), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::CallerStack(0)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(4),
+            InstructionArgument::CallerStack(0),
+        ),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "1\n2\n4\n");
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::{load_main, reg};
+use emulated_cpu::cpu::{CpuError, CpuInstruction, InstructionArgument, Register};
+
+#[test]
+fn a_syscall_runs_the_registered_handler() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(21), reg(Register::A)),
+        CpuInstruction::Syscall(InstructionArgument::Value(1)),
+        CpuInstruction::Print(reg(Register::A)),
+    ]);
+    cpu.register_syscall(1, |registers, _| {
+        let a = Register::A as usize;
+        registers.general[a] *= 2;
+    });
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "42\n");
+}
+
+#[test]
+fn a_syscall_without_a_handler_names_its_number() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(9), reg(Register::B)),
+        CpuInstruction::Syscall(reg(Register::B)),
+    ]);
+    cpu.register_syscall(1, |_, _| {});
+    let error = cpu.execute().unwrap_err();
+
+    assert!(matches!(error.inner(), CpuError::UnknownSyscall(9)));
+    assert!(error.to_string().contains("No handler for syscall 9"));
+}
+
+#[test]
+fn an_emulated_loop_calls_a_host_function_collecting_values() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Mul(reg(Register::A), reg(Register::A)),
+        CpuInstruction::CallArgs("collect".into(), vec![reg(Register::Res)]),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(5)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+        CpuInstruction::Call("count".into()),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+    ]);
+    let collected = Rc::new(RefCell::new(Vec::new()));
+    let sink = collected.clone();
+    cpu.register_host_fn("collect", move |context| {
+        sink.borrow_mut().extend_from_slice(context.arguments);
+        None
+    });
+    let count = collected.clone();
+    cpu.register_host_fn("count", move |_| Some(count.borrow().len() as u16));
+    cpu.execute().unwrap();
+
+    assert_eq!(*collected.borrow(), [1, 4, 9, 16, 25]);
+    assert_eq!(cpu.get_register(Register::B), 5);
+}
+
+#[test]
+fn a_host_function_does_not_get_a_sub_stack() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(8), InstructionArgument::Stack(0)),
+        CpuInstruction::Call("host".into()),
+    ]);
+    let depths = Rc::new(RefCell::new(Vec::new()));
+    let seen = depths.clone();
+    cpu.register_host_fn("host", move |context| {
+        let memory = &context.memory;
+        seen.borrow_mut()
+            .push((memory.get_depth(), memory.read_data(0).unwrap()));
+        None
+    });
+    cpu.execute().unwrap();
+
+    // The host function sees the sub stack of "main"
+    assert_eq!(*depths.borrow(), [(1, 8)]);
+}
+
+/** Multiplies the first two arguments and adds the third */
+fn multiply_accumulate(arguments: &[u16]) -> u16 {
+    arguments[0] * arguments[1] + arguments[2]
+}
+
+#[test]
+fn a_custom_opcode_runs_its_handler() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(6), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(4), InstructionArgument::Stack(0)),
+        CpuInstruction::Custom(
+            0x10,
+            vec![
+                reg(Register::A),
+                InstructionArgument::Value(7),
+                InstructionArgument::Stack(0),
+            ],
+        ),
+        CpuInstruction::Print(reg(Register::Res)),
+    ]);
+    cpu.register_custom_op(0x10, |arguments, _, _| multiply_accumulate(arguments));
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "46\n");
+}
+
+#[test]
+fn a_custom_opcode_without_a_handler_is_illegal() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Custom(0x11, vec![])]);
+    cpu.register_custom_op(0x10, |arguments, _, _| multiply_accumulate(arguments));
+    let error = cpu.execute().unwrap_err();
+
+    assert!(matches!(error.inner(), CpuError::IllegalInstruction(0x11)));
+    assert!(error.to_string().contains("no handler for opcode 17"));
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use common::load;
+use emulated_cpu::cpu::{
+    CpuError, CpuInstruction, FAULT_DIVISION_BY_ZERO, InstructionArgument, Register,
+};
+
+#[test]
+fn an_interrupt_raised_by_the_host_runs_before_the_next_instruction() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Print(InstructionArgument::Value(3)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("handler".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(9)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.set_interrupt_handler(1, "handler");
+    cpu.start().unwrap();
+
+    // Calling "main", then printing 1
+    assert!(cpu.step());
+    assert!(cpu.step());
+    cpu.raise_interrupt(1).unwrap();
+    cpu.resume().unwrap();
+    assert_eq!(output.text(), "1\n9\n2\n3\n");
+}
+
+#[test]
+fn an_interrupt_raised_by_the_host_needs_a_handler() {
+    let (mut cpu, _) = load::<u16>(&[CpuInstruction::Fn("main".into(), 0), CpuInstruction::Ret()]);
+    cpu.start().unwrap();
+
+    assert!(matches!(
+        cpu.raise_interrupt(5),
+        Err(CpuError::UnhandledInterrupt(5))
+    ));
+}
+
+#[test]
+fn int_runs_the_handler_and_resumes_after_it() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Int(InstructionArgument::Value(3)),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("handler".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(9)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.set_interrupt_handler(3, "handler");
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "1\n9\n2\n");
+}
+
+#[test]
+fn int_without_a_handler_stops_the_cpu() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Int(InstructionArgument::Value(4)),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.set_interrupt_handler(3, "main");
+    let error = cpu.execute().unwrap_err();
+
+    assert!(matches!(error.inner(), CpuError::UnhandledInterrupt(4)));
+    assert!(error.to_string().contains("No handler for interrupt 4"));
+    assert_eq!(output.text(), "");
+}
+
+/** A "main" looping `iterations` times, and a "tick" handler counting its calls in the global address 0 */
+fn counting_ticks(iterations: u16) -> Vec<CpuInstruction> {
+    vec![
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(iterations),
+            InstructionArgument::Register(Register::A),
+        ),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Nop(),
+        CpuInstruction::Loop(InstructionArgument::Register(Register::A), "loop".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("tick".into(), 0),
+        CpuInstruction::Inc(InstructionArgument::Global(0)),
+        CpuInstruction::Ret(),
+    ]
+}
+
+#[test]
+fn the_timer_calls_its_handler_every_period() {
+    let (mut cpu, _) = load(&counting_ticks(1000));
+    cpu.set_timer_interrupt(100, "tick");
+    cpu.execute().unwrap();
+
+    // The handlers lasted 3 cycles each, on top of the 3003 cycles of "main"
+    let ticks = cpu.memory().read_global(0).unwrap();
+    assert_eq!(ticks, 30);
+    assert_eq!(cpu.get_cycles(), 3003 + 30 * 3);
+    assert_eq!(cpu.get_register(Register::A), 0);
+}
+
+#[test]
+fn a_disabled_timer_never_fires() {
+    let (mut cpu, _) = load(&counting_ticks(1000));
+    cpu.set_timer_interrupt(100, "tick");
+    cpu.set_timer_interrupt(0, "tick");
+    cpu.execute().unwrap();
+
+    assert_eq!(cpu.memory().read_global(0).unwrap(), 0);
+    assert_eq!(cpu.get_cycles(), 3003);
+}
+
+#[test]
+fn a_timer_firing_while_interrupts_are_disabled_is_delivered_once_after_sti() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Cli(),
+        // Three periods elapse meanwhile
+        CpuInstruction::Wait(InstructionArgument::Value(100)),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Sti(),
+        CpuInstruction::Print(InstructionArgument::Value(2)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("tick".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(9)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.set_timer_interrupt(30, "tick");
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "1\n9\n2\n");
+}
+
+/** Prints the fault code, then replaces the result of the faulting instruction by 77 */
+fn fault_handler() -> Vec<CpuInstruction> {
+    vec![
+        CpuInstruction::Fn("fault".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Register(Register::Res)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(77),
+            InstructionArgument::Register(Register::Res),
+        ),
+        CpuInstruction::Ret(),
+    ]
+}
+
+/** Divides 10 by 0 in "main", then prints "res" */
+fn dividing_by_zero() -> Vec<CpuInstruction> {
+    vec![
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Div(
+            InstructionArgument::Value(10),
+            InstructionArgument::Value(0),
+        ),
+        CpuInstruction::Print(InstructionArgument::Register(Register::Res)),
+        CpuInstruction::Ret(),
+    ]
+}
+
+#[test]
+fn the_fault_handler_resumes_after_the_faulting_instruction() {
+    let (mut cpu, output) = load(&[dividing_by_zero(), fault_handler()].concat());
+    cpu.set_fault_handler("fault");
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), format!("{FAULT_DIVISION_BY_ZERO}\n77\n"));
+}
+
+#[test]
+fn a_fault_without_a_handler_stops_the_cpu() {
+    let (mut cpu, output) = load(&[dividing_by_zero(), fault_handler()].concat());
+    let error = cpu.execute().unwrap_err();
+
+    assert!(matches!(error.inner(), CpuError::DivisionByZero));
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn a_fault_in_a_called_function_returns_into_that_function() {
+    let program = [
+        vec![
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::Call("divide".into()),
+            CpuInstruction::Print(InstructionArgument::Value(2)),
+            CpuInstruction::Ret(),
+            CpuInstruction::Fn("divide".into(), 0),
+            CpuInstruction::Div(
+                InstructionArgument::Value(10),
+                InstructionArgument::Value(0),
+            ),
+            CpuInstruction::Print(InstructionArgument::Register(Register::Res)),
+            CpuInstruction::Ret(),
+        ],
+        fault_handler(),
+    ];
+    let (mut cpu, output) = load(&program.concat());
+    cpu.set_fault_handler("fault");
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), format!("{FAULT_DIVISION_BY_ZERO}\n77\n2\n"));
+}
+
+#[test]
+fn a_fault_in_an_if_block_resumes_the_rest_of_the_block() {
+    let program = [
+        vec![
+            CpuInstruction::Fn("main".into(), 0),
+            CpuInstruction::IfBlock(
+                InstructionArgument::Value(1),
+                vec![
+                    CpuInstruction::Print(InstructionArgument::Value(1)),
+                    CpuInstruction::Div(
+                        InstructionArgument::Value(10),
+                        InstructionArgument::Value(0),
+                    ),
+                    CpuInstruction::Print(InstructionArgument::Register(Register::Res)),
+                ],
+                vec![],
+            ),
+            CpuInstruction::Print(InstructionArgument::Value(3)),
+            CpuInstruction::Ret(),
+        ],
+        fault_handler(),
+    ];
+    let (mut cpu, output) = load(&program.concat());
+    cpu.set_fault_handler("fault");
+    cpu.execute().unwrap();
+
+    assert_eq!(
+        output.text(),
+        format!("1\n{FAULT_DIVISION_BY_ZERO}\n77\n3\n")
+    );
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use common::{load, load_main, reg, run_main};
+use emulated_cpu::cpu::{CpuError, CpuInstruction, InstructionArgument, MemoryError, Register};
+
+#[test]
+fn print_writes_decimal_values_and_newlines_to_the_output() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Print(InstructionArgument::Value(0)),
+        CpuInstruction::Mov(InstructionArgument::Value(0xFFFF), reg(Register::A)),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(42),
+            InstructionArgument::Stack(0),
+        ),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+    ]);
+
+    assert_eq!(output.as_bytes(), b"0\n65535\n42\n");
+}
+
+#[test]
+fn print_char_writes_single_bytes() {
+    let mut body: Vec<_> = "hello"
+        .bytes()
+        .map(|byte| CpuInstruction::PrintChar(InstructionArgument::Value(byte.into())))
+        .collect();
+    // Only the low byte of the value is written
+    body.push(CpuInstruction::PrintChar(InstructionArgument::Value(0x121)));
+    let (_, output) = run_main(body);
+
+    assert_eq!(output.as_bytes(), b"hello!");
+}
+
+/** Stores "Hi!" in the first three slots of the sub stack, then runs the instruction */
+fn after_storing_hi(instruction: CpuInstruction) -> Vec<CpuInstruction> {
+    let mut body: Vec<_> = (0..)
+        .zip("Hi!".bytes())
+        .map(|(address, byte)| {
+            CpuInstruction::Mov(
+                InstructionArgument::Value(byte.into()),
+                InstructionArgument::Stack(address),
+            )
+        })
+        .collect();
+    body.push(CpuInstruction::Add(
+        InstructionArgument::Value(2),
+        InstructionArgument::Value(3),
+    ));
+    body.push(instruction);
+
+    body
+}
+
+#[test]
+fn print_str_writes_the_bytes_of_consecutive_slots() {
+    let (cpu, output) = run_main(after_storing_hi(CpuInstruction::PrintStr(
+        InstructionArgument::Stack(0),
+        InstructionArgument::Value(3),
+    )));
+    assert_eq!(output.as_bytes(), b"Hi!");
+    assert_eq!(cpu.get_register(Register::Res), 5);
+
+    let (_, output) = run_main(after_storing_hi(CpuInstruction::PrintStr(
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(2),
+    )));
+    assert_eq!(output.as_bytes(), b"i!");
+}
+
+#[test]
+fn print_str_past_the_end_of_a_sized_frame_stops_the_cpu() {
+    let mut instructions = vec![CpuInstruction::Fn("main".into(), 3)];
+    instructions.extend(after_storing_hi(CpuInstruction::PrintStr(
+        InstructionArgument::Stack(1),
+        InstructionArgument::Value(3),
+    )));
+    instructions.push(CpuInstruction::Ret());
+    let (mut cpu, output) = load(&instructions);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Memory(MemoryError::OutOfBounds {
+            address: 3,
+            size: 3
+        })
+    ));
+    assert_eq!(output.text(), "");
+}
+
+/** Reads two values into "a" and echoes each of them, with the values given as input */
+fn echo(input: &[u64]) -> (Result<(), CpuError>, String) {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Read(reg(Register::A)),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Read(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+    ]);
+    cpu.set_input(Box::new(VecDeque::from(input.to_vec())));
+
+    let result = cpu
+        .execute()
+        .map(|_| ())
+        .map_err(|error| error.inner().clone());
+    (result, output.text())
+}
+
+#[test]
+fn read_echoes_the_input_values() {
+    let (result, output) = echo(&[3, 65535]);
+
+    assert!(result.is_ok());
+    assert_eq!(output, "3\n65535\n");
+}
+
+#[test]
+fn reading_past_the_end_of_the_input_stops_the_cpu() {
+    let (result, output) = echo(&[3]);
+
+    assert!(matches!(result, Err(CpuError::NoInput)));
+    assert_eq!(output, "3\n");
+}
+
+#[test]
+fn reading_a_value_too_large_for_a_word_stops_the_cpu() {
+    let (result, output) = echo(&[70_000, 1]);
+
+    assert!(matches!(result, Err(CpuError::NoInput)));
+    assert_eq!(output, "");
+}
+
+/** Counts from 1 to 3 in "a", printing every value */
+fn counting_program() -> Vec<CpuInstruction> {
+    vec![
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(3)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+    ]
+}
+
+#[test]
+fn the_trace_and_the_prints_share_the_output() {
+    let (mut cpu, output) = load_main(counting_program());
+    cpu.trace = true;
+    cpu.execute().unwrap();
+
+    let trace: Vec<_> = output.text().lines().map(String::from).collect();
+    assert_eq!(trace.len(), 20);
+    assert_eq!(trace[..5], ["0: 0", "1: 0", "2: 1", "1", "3: 1"]);
+    let printed: Vec<_> = trace.iter().filter(|line| !line.contains(':')).collect();
+    assert_eq!(printed, ["1", "2", "3"]);
+}
+
+#[test]
+fn a_sink_output_silences_everything() {
+    let (mut cpu, output) = load_main(counting_program());
+    cpu.trace = true;
+    cpu.verbose = true;
+    cpu.set_output(Box::new(std::io::sink()));
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "");
+    assert_eq!(cpu.get_register(Register::A), 3);
+}
+
+#[test]
+fn strings_round_trip_through_the_data_segment() {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::PrintStr(InstructionArgument::Data(0), InstructionArgument::Value(14)),
+        CpuInstruction::PrintStr(InstructionArgument::Data(14), InstructionArgument::Value(1)),
+    ]);
+    // Stored as UTF-8 bytes, "ö" taking two slots
+    assert_eq!(cpu.append_string("Hello, wörld!"), (0, 14));
+    assert_eq!(cpu.append_string("\n"), (14, 1));
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "Hello, wörld!\n");
+}
@@ -0,0 +1,581 @@
+mod common;
+
+use common::{SharedOutput, fail_main, load, load_main, reg, run_main};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, InstructionArgument, MemoryBacking, MemoryError, Register,
+};
+
+/** The first `length` slots of the global memory */
+fn globals(cpu: &Cpu16, length: u16) -> Vec<u16> {
+    (0..length)
+        .map(|address| cpu.memory().read_global(address).unwrap())
+        .collect()
+}
+
+/** Copies between the global slots, which start with 1 2 3 4 5 */
+fn copy_globals(destination: u16, source: u16, length: u16) -> Vec<u16> {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::MemCopy(
+        InstructionArgument::Global(destination),
+        InstructionArgument::Global(source),
+        InstructionArgument::Value(length),
+    )]);
+    cpu.preload_global(&[1, 2, 3, 4, 5]).unwrap();
+
+    cpu.execute().unwrap();
+    globals(&cpu, 5)
+}
+
+#[test]
+fn mem_copy_to_a_later_overlapping_range() {
+    assert_eq!(copy_globals(1, 0, 4), [1, 1, 2, 3, 4]);
+}
+
+#[test]
+fn mem_copy_to_an_earlier_overlapping_range() {
+    assert_eq!(copy_globals(0, 1, 4), [2, 3, 4, 5, 5]);
+}
+
+#[test]
+fn mem_copy_of_zero_slots_copies_nothing() {
+    assert_eq!(copy_globals(0, 3, 0), [1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn mem_copy_out_of_bounds_copies_nothing() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Call("sized".into())]);
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("sized".into(), 4),
+        CpuInstruction::MemCopy(
+            InstructionArgument::Stack(2),
+            InstructionArgument::Global(0),
+            InstructionArgument::Value(3),
+        ),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+    cpu.preload_global(&[1, 2, 3]).unwrap();
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Memory(MemoryError::OutOfBounds {
+            address: 4,
+            size: 4
+        })
+    ));
+    assert_eq!(cpu.memory().read_data(2).unwrap(), 0);
+    assert_eq!(cpu.memory().read_data(3).unwrap(), 0);
+}
+
+#[test]
+fn mem_copy_beyond_the_memory_limits_copies_nothing() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::MemCopy(
+        InstructionArgument::Stack(4),
+        InstructionArgument::Global(0),
+        InstructionArgument::Value(10),
+    )]);
+    cpu.preload_global(&[1; 10]).unwrap();
+    cpu.set_memory_limits(8, 1000);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Memory(MemoryError::FrameLimitExceeded { limit: 8, .. })
+    ));
+    assert_eq!(cpu.memory().get_current_sub_stack().data.len(), 0);
+}
+
+#[test]
+fn pop_returns_the_values_in_reverse_order() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Push(InstructionArgument::Value(1)),
+        CpuInstruction::Push(InstructionArgument::Value(2)),
+        CpuInstruction::Pop(reg(Register::A)),
+        CpuInstruction::Pop(InstructionArgument::Stack(3)),
+        CpuInstruction::Print(reg(Register::A)),
+        CpuInstruction::Print(InstructionArgument::Stack(3)),
+    ]);
+
+    assert_eq!(output, "2\n1\n");
+}
+
+#[test]
+fn popping_an_empty_sub_stack_stops_the_cpu() {
+    assert!(matches!(
+        fail_main(vec![CpuInstruction::Pop(reg(Register::A))]),
+        CpuError::StackUnderflow
+    ));
+    assert!(matches!(
+        fail_main(vec![
+            CpuInstruction::Push(InstructionArgument::Value(1)),
+            CpuInstruction::Pop(reg(Register::A)),
+            CpuInstruction::Pop(reg(Register::A)),
+        ]),
+        CpuError::StackUnderflow
+    ));
+}
+
+#[test]
+fn indirect_arguments_walk_an_array() {
+    let mut body: Vec<_> = (10..15)
+        .zip([3, 1, 4, 1, 5])
+        .map(|(address, value)| {
+            CpuInstruction::Mov(
+                InstructionArgument::Value(value),
+                InstructionArgument::Stack(address),
+            )
+        })
+        .collect();
+    body.extend([
+        CpuInstruction::Mov(InstructionArgument::Value(10), reg(Register::B)),
+        CpuInstruction::Label("sum".into()),
+        CpuInstruction::Add(reg(Register::C), InstructionArgument::Indirect(Register::B)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::C)),
+        CpuInstruction::Inc(reg(Register::B)),
+        CpuInstruction::Lt(reg(Register::B), InstructionArgument::Value(15)),
+        CpuInstruction::Jnz(reg(Register::Res), "sum".into()),
+        // Writing through the index too, right after the array
+        CpuInstruction::Mov(reg(Register::C), InstructionArgument::Indirect(Register::B)),
+        CpuInstruction::Print(InstructionArgument::Stack(15)),
+    ]);
+    let (_, output) = run_main(body);
+
+    assert_eq!(output, "14\n");
+}
+
+/** The field of the record whose address is in "a" */
+fn field(offset: u16) -> InstructionArgument {
+    InstructionArgument::StackOffset {
+        base: Register::A,
+        offset,
+    }
+}
+
+#[test]
+fn stack_offsets_write_and_read_the_fields_of_a_record() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(20), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(3), field(0)),
+        CpuInstruction::Mov(InstructionArgument::Value(4), field(1)),
+        CpuInstruction::Mul(field(0), field(1)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Brk(),
+    ]);
+
+    assert_eq!(output, "12\n");
+    assert_eq!(cpu.memory().read_data(20).unwrap(), 3);
+    assert_eq!(cpu.memory().read_data(21).unwrap(), 4);
+    assert_eq!(cpu.get_register(Register::A), 20);
+}
+
+#[test]
+fn a_stack_offset_wraps_around_past_the_last_address() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(u16::MAX), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(7), field(2)),
+        CpuInstruction::Brk(),
+    ]);
+
+    assert_eq!(cpu.memory().read_data(1).unwrap(), 7);
+}
+
+#[test]
+fn a_callee_leaves_a_global_value_behind_for_its_caller() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Call("produce".into()),
+        CpuInstruction::Print(InstructionArgument::Global(3)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("produce".into(), 0),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(42),
+            InstructionArgument::Stack(3),
+        ),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(7),
+            InstructionArgument::Global(3),
+        ),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "7\n");
+    assert_eq!(globals(&cpu, 4), [0, 0, 0, 7]);
+}
+
+#[test]
+fn a_callee_writes_an_out_parameter_of_its_caller() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(2)),
+        CpuInstruction::Call("double".into()),
+        CpuInstruction::Print(InstructionArgument::Stack(2)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("double".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(5), InstructionArgument::Stack(2)),
+        CpuInstruction::Mul(InstructionArgument::Stack(2), InstructionArgument::Value(2)),
+        CpuInstruction::Mov(reg(Register::Res), InstructionArgument::CallerStack(2)),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "10\n");
+}
+
+#[test]
+fn main_has_no_caller_stack() {
+    let error = fail_main(vec![CpuInstruction::Print(
+        InstructionArgument::CallerStack(0),
+    )]);
+
+    assert!(matches!(
+        error,
+        CpuError::Memory(MemoryError::NoCallerFrame)
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Cannot access the caller's sub stack, there is no caller"
+    );
+}
+
+/** Moves the values into the global slots from the address */
+fn store_globals(address: u16, values: &[u16]) -> Vec<CpuInstruction> {
+    (address..)
+        .zip(values)
+        .map(|(address, &value)| {
+            CpuInstruction::Mov(
+                InstructionArgument::Value(value),
+                InstructionArgument::Global(address),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn reusing_a_freed_block_leaves_the_other_blocks_intact() {
+    let mut body = vec![
+        CpuInstruction::Alloc(InstructionArgument::Value(3)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::A)),
+        CpuInstruction::Alloc(InstructionArgument::Value(3)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::B)),
+    ];
+    body.extend(store_globals(0x8000, &[1, 2, 3]));
+    body.extend(store_globals(0x8003, &[4, 5, 6]));
+    body.extend([
+        CpuInstruction::Free(reg(Register::A)),
+        CpuInstruction::Alloc(InstructionArgument::Value(2)),
+        CpuInstruction::Mov(reg(Register::Res), reg(Register::C)),
+        CpuInstruction::Mov(InstructionArgument::Global(0x8000), reg(Register::D)),
+    ]);
+    body.extend(store_globals(0x8000, &[0xFF, 0xFF]));
+    body.push(CpuInstruction::Brk());
+    let (cpu, _) = run_main(body);
+
+    assert_eq!(cpu.get_register(Register::A), 0x8000);
+    assert_eq!(cpu.get_register(Register::B), 0x8003);
+    // The freed block is reused, filled with 0s again
+    assert_eq!(cpu.get_register(Register::C), 0x8000);
+    assert_eq!(cpu.get_register(Register::D), 0);
+    let surviving: Vec<_> = (0x8003..0x8006)
+        .map(|address| cpu.memory().read_global(address).unwrap())
+        .collect();
+    assert_eq!(surviving, [4, 5, 6]);
+}
+
+#[test]
+fn freeing_twice_or_a_bogus_address_stops_the_cpu() {
+    let error = fail_main(vec![
+        CpuInstruction::Alloc(InstructionArgument::Value(2)),
+        CpuInstruction::Free(reg(Register::Res)),
+        CpuInstruction::Free(InstructionArgument::Value(0x8000)),
+    ]);
+    assert!(matches!(
+        error,
+        CpuError::Memory(MemoryError::InvalidFree(0x8000))
+    ));
+
+    let error = fail_main(vec![
+        CpuInstruction::Alloc(InstructionArgument::Value(2)),
+        CpuInstruction::Free(InstructionArgument::Value(0x8001)),
+    ]);
+    assert!(matches!(
+        error,
+        CpuError::Memory(MemoryError::InvalidFree(0x8001))
+    ));
+}
+
+/** Fills global slots, which start with 1 2 3 4 5 */
+fn set_globals(destination: u16, value: u16, length: u16) -> Vec<u16> {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::MemSet(
+        InstructionArgument::Global(destination),
+        InstructionArgument::Value(value),
+        InstructionArgument::Value(length),
+    )]);
+    cpu.preload_global(&[1, 2, 3, 4, 5]).unwrap();
+
+    cpu.execute().unwrap();
+    globals(&cpu, 5)
+}
+
+#[test]
+fn mem_set_fills_the_range_only() {
+    assert_eq!(set_globals(1, 9, 3), [1, 9, 9, 9, 5]);
+    assert_eq!(set_globals(0, 0, 5), [0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn mem_set_of_zero_slots_writes_nothing() {
+    assert_eq!(set_globals(2, 9, 0), [1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn mem_set_lasts_a_single_cycle() {
+    let (cpu, _) = run_main(vec![CpuInstruction::MemSet(
+        InstructionArgument::Stack(0),
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(100),
+    )]);
+
+    // The call to "main" and its RET last a cycle each
+    assert_eq!(cpu.get_cycles(), 1 + 2);
+}
+
+#[test]
+fn mem_set_beyond_the_memory_limits_writes_nothing() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::MemSet(
+        InstructionArgument::Stack(4),
+        InstructionArgument::Value(1),
+        InstructionArgument::Value(10),
+    )]);
+    cpu.set_memory_limits(8, 1000);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Memory(MemoryError::FrameLimitExceeded { limit: 8, .. })
+    ));
+    assert_eq!(cpu.memory().get_current_sub_stack().data.len(), 0);
+}
+
+#[test]
+fn programs_look_up_a_table_of_the_data_segment() {
+    let squares: Vec<u16> = (0..10).map(|value| value * value).collect();
+    // The table follows two other constants
+    let base = 2;
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Print(InstructionArgument::Data(base + 3)),
+        CpuInstruction::Print(InstructionArgument::Data(base + 9)),
+        CpuInstruction::Add(
+            InstructionArgument::Data(0),
+            InstructionArgument::Data(base + 7),
+        ),
+        CpuInstruction::Print(reg(Register::Res)),
+    ]);
+    assert_eq!(cpu.append_data(&[100, 200]), 0);
+    assert_eq!(cpu.append_data(&squares), base);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "9\n81\n149\n");
+}
+
+#[test]
+fn writing_the_data_segment_stops_the_cpu() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Mov(
+        InstructionArgument::Value(5),
+        InstructionArgument::Data(2),
+    )]);
+    cpu.append_data(&[1, 2, 3]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error.inner(), CpuError::WriteToReadOnly(2)));
+}
+
+#[test]
+fn reading_past_the_data_segment_stops_the_cpu() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Print(InstructionArgument::Data(3))]);
+    cpu.append_data(&[1, 2, 3]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::DataOutOfBounds {
+            address: 3,
+            size: 3
+        }
+    ));
+}
+
+#[test]
+fn reading_a_slot_never_written_does_not_grow_the_sub_stack() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(2)),
+        CpuInstruction::Mov(InstructionArgument::Stack(5000), reg(Register::A)),
+        CpuInstruction::Add(
+            InstructionArgument::Stack(60000),
+            InstructionArgument::Value(1),
+        ),
+        CpuInstruction::Brk(),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::A), 0);
+    assert_eq!(cpu.get_register(Register::Res), 1);
+    assert_eq!(cpu.memory().get_current_sub_stack().data.len(), 3);
+}
+
+#[test]
+fn reserve_grows_the_sub_stack_explicitly() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Reserve(InstructionArgument::Value(10)),
+        CpuInstruction::Brk(),
+    ]);
+
+    let data = &cpu.memory().get_current_sub_stack().data;
+    assert_eq!(data.len(), 10);
+    assert_eq!(data.to_vec(), [0; 10]);
+}
+
+#[test]
+fn writing_a_large_address_fills_the_slots_before_it_with_0s() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(3)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(7),
+            InstructionArgument::Stack(60000),
+        ),
+        CpuInstruction::Brk(),
+    ]);
+    cpu.set_memory_limits(65536, 1024 * 1024);
+    cpu.execute().unwrap();
+
+    let data = cpu.memory().get_current_sub_stack().data.to_vec();
+    assert_eq!(data.len(), 60001);
+    assert_eq!(data[3], 1);
+    assert_eq!(data[60000], 7);
+    assert_eq!(data.iter().filter(|&&value| value != 0).count(), 2);
+}
+
+/** Runs "main" writing the stack address with the memory limits */
+fn write_with_limits(address: u16, max_frame_size: usize) -> Result<(), CpuError> {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Mov(
+        InstructionArgument::Value(1),
+        InstructionArgument::Stack(address),
+    )]);
+    cpu.set_memory_limits(max_frame_size, 1000);
+
+    cpu.execute()
+        .map(|_| ())
+        .map_err(|error| error.inner().clone())
+}
+
+#[test]
+fn a_sub_stack_holds_up_to_the_frame_limit() {
+    assert!(write_with_limits(7, 8).is_ok());
+    assert!(matches!(
+        write_with_limits(8, 8),
+        Err(CpuError::Memory(MemoryError::FrameLimitExceeded {
+            address: 8,
+            limit: 8
+        }))
+    ));
+}
+
+#[test]
+fn the_sub_stacks_together_hold_up_to_the_total_memory_limit() {
+    let (mut cpu, _) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(7)),
+        CpuInstruction::Call("f".into()),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("f".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(3)),
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(4)),
+        CpuInstruction::Ret(),
+    ]);
+    // Each sub stack stays under the frame limit
+    cpu.set_memory_limits(8, 12);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::Memory(MemoryError::MemoryLimitExceeded {
+            address: 4,
+            limit: 12
+        })
+    ));
+    assert!(error.to_string().contains("in f at instruction 6"));
+}
+
+/** Runs "main" made of the instructions with the memory backing, it stops at a BRK closing the body */
+fn run_with_backing(backing: MemoryBacking, mut body: Vec<CpuInstruction>) -> (Cpu16, String) {
+    let output = SharedOutput::default();
+    let mut cpu = Cpu16::with_memory_backing(0, backing);
+    cpu.set_output(Box::new(output.clone()));
+    body.insert(0, CpuInstruction::Fn("main".into(), 0));
+    body.push(CpuInstruction::Brk());
+    cpu.append_instructions(&body).unwrap();
+    cpu.execute().unwrap();
+
+    (cpu, output.text())
+}
+
+#[test]
+fn dense_and_sparse_memories_run_programs_identically() {
+    let program = vec![
+        CpuInstruction::Mov(InstructionArgument::Value(3), InstructionArgument::Stack(9)),
+        CpuInstruction::Mov(
+            InstructionArgument::Value(4),
+            InstructionArgument::Global(2),
+        ),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Add(
+            InstructionArgument::Stack(9),
+            InstructionArgument::Global(2),
+        ),
+        CpuInstruction::Mov(reg(Register::Res), InstructionArgument::Stack(9)),
+        CpuInstruction::Push(reg(Register::Res)),
+        CpuInstruction::Loop(InstructionArgument::Global(2), "loop".into()),
+        CpuInstruction::Alloc(InstructionArgument::Value(2)),
+        CpuInstruction::Mov(
+            InstructionArgument::Stack(1),
+            InstructionArgument::Global(0x8001),
+        ),
+        CpuInstruction::Print(InstructionArgument::Stack(9)),
+        CpuInstruction::Print(InstructionArgument::Stack(500)),
+    ];
+    let (dense, dense_output) = run_with_backing(MemoryBacking::Dense, program.clone());
+    let (sparse, sparse_output) = run_with_backing(MemoryBacking::Sparse, program);
+
+    assert_eq!(dense_output, "13\n0\n");
+    assert_eq!(dense_output, sparse_output);
+    assert_eq!(dense.snapshot().registers(), sparse.snapshot().registers());
+    let frame = |cpu: &Cpu16| cpu.memory().get_current_sub_stack().data.to_vec();
+    assert_eq!(frame(&dense), frame(&sparse));
+    for address in [0, 2, 0x8000, 0x8001, 0x8002] {
+        assert_eq!(
+            dense.memory().read_global(address).unwrap(),
+            sparse.memory().read_global(address).unwrap()
+        );
+    }
+}
+
+#[test]
+fn a_sparse_memory_only_stores_the_slots_written() {
+    let (cpu, output) = run_with_backing(
+        MemoryBacking::Sparse,
+        vec![
+            CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(0)),
+            CpuInstruction::Mov(
+                InstructionArgument::Value(2),
+                InstructionArgument::Stack(65000),
+            ),
+            CpuInstruction::Print(InstructionArgument::Stack(65000)),
+            CpuInstruction::Print(InstructionArgument::Stack(30000)),
+        ],
+    );
+
+    assert_eq!(output, "2\n0\n");
+    let data = &cpu.memory().get_current_sub_stack().data;
+    assert_eq!(data.stored_len(), 2);
+    assert_eq!(data.len(), 65001);
+    assert_eq!(cpu.memory().get_total_memory(), 2);
+}
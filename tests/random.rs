@@ -0,0 +1,48 @@
+mod common;
+
+use common::{load_main, reg};
+use emulated_cpu::cpu::{CpuInstruction, InstructionArgument, Register};
+
+/** Prints `count` values drawn with RAND under the bound, using the seed */
+fn draw(seed: u64, bound: u16, count: u16) -> Vec<u16> {
+    let (mut cpu, output) = load_main(vec![
+        CpuInstruction::Label("draw".into()),
+        CpuInstruction::Rand(InstructionArgument::Value(bound)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(count)),
+        CpuInstruction::Jnz(reg(Register::Res), "draw".into()),
+    ]);
+    cpu.set_rng_seed(seed);
+    cpu.execute().unwrap();
+
+    output
+        .text()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn a_fixed_seed_gives_the_same_sequence() {
+    assert_eq!(draw(42, 100, 5), [74, 71, 54, 36, 62]);
+    assert_eq!(draw(42, 100, 5), draw(42, 100, 5));
+    assert_ne!(draw(42, 100, 5), draw(43, 100, 5));
+}
+
+#[test]
+fn the_values_stay_under_the_bound() {
+    let values = draw(7, 6, 1000);
+
+    assert_eq!(values.len(), 1000);
+    assert!(values.iter().all(|&value| value < 6));
+    // Every face of the die comes up at least once
+    assert!((0..6).all(|face| values.contains(&face)));
+}
+
+#[test]
+fn a_zero_bound_draws_from_the_whole_word() {
+    let values = draw(7, 0, 100);
+
+    assert!(values.iter().any(|&value| value > u8::MAX as u16));
+}
@@ -0,0 +1,325 @@
+mod common;
+
+use common::{SharedOutput, load, load_main, reg, run_main};
+use emulated_cpu::cpu::{
+    Cpu16, CpuError, CpuInstruction, FloatRegister, InstructionArgument, Register,
+};
+
+#[test]
+fn swap_exchanges_two_registers() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(2), reg(Register::B)),
+        CpuInstruction::Swap(reg(Register::A), reg(Register::B)),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::A), 2);
+    assert_eq!(cpu.get_register(Register::B), 1);
+}
+
+#[test]
+fn swap_exchanges_two_stack_slots() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), InstructionArgument::Stack(0)),
+        CpuInstruction::Mov(InstructionArgument::Value(2), InstructionArgument::Stack(1)),
+        CpuInstruction::Swap(InstructionArgument::Stack(0), InstructionArgument::Stack(1)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(1)),
+    ]);
+
+    assert_eq!(output, "2\n1\n");
+}
+
+#[test]
+fn swap_exchanges_a_register_and_a_stack_slot() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(2), InstructionArgument::Stack(0)),
+        CpuInstruction::Swap(reg(Register::A), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::A), 2);
+    assert_eq!(output, "1\n");
+}
+
+#[test]
+fn swap_computes_both_addresses_before_writing() {
+    let (cpu, output) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(5), reg(Register::A)),
+        CpuInstruction::Mov(InstructionArgument::Value(9), InstructionArgument::Stack(5)),
+        CpuInstruction::Mov(InstructionArgument::Value(7), InstructionArgument::Stack(9)),
+        CpuInstruction::Swap(reg(Register::A), InstructionArgument::Indirect(Register::A)),
+        CpuInstruction::Print(InstructionArgument::Stack(5)),
+        CpuInstruction::Print(InstructionArgument::Stack(9)),
+    ]);
+
+    assert_eq!(cpu.get_register(Register::A), 9);
+    assert_eq!(output, "5\n7\n");
+}
+
+#[test]
+fn a_failed_swap_writes_neither_destination() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::A)),
+        CpuInstruction::Swap(reg(Register::A), InstructionArgument::Value(9)),
+    ]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(error.inner(), CpuError::InvalidDestination(9)));
+    assert_eq!(cpu.get_register(Register::A), 1);
+}
+
+/** A program moving a value to "res" and printing it */
+fn moving_to_res() -> Vec<CpuInstruction> {
+    vec![
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Mov(InstructionArgument::Value(5), reg(Register::Res)),
+        CpuInstruction::Print(reg(Register::Res)),
+        CpuInstruction::Ret(),
+    ]
+}
+
+#[test]
+fn moving_to_res_is_allowed_by_default() {
+    let (mut cpu, output) = load(&moving_to_res());
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "5\n");
+}
+
+#[test]
+fn moving_to_res_is_rejected_when_protected_and_nothing_is_loaded() {
+    let output = SharedOutput::default();
+    let mut cpu = Cpu16::with_output(0, Box::new(output.clone()));
+    cpu.protect_res = true;
+
+    let error = cpu.append_instructions(&moving_to_res()).unwrap_err();
+    assert!(matches!(error, CpuError::ProtectedResult));
+
+    cpu.append_instructions(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Print(InstructionArgument::Value(1)),
+        CpuInstruction::Ret(),
+    ])
+    .unwrap();
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "1\n");
+}
+
+#[test]
+fn nop_leaves_the_registers_and_the_sub_stack_untouched() {
+    let (mut cpu, _) = load_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(3), InstructionArgument::Stack(0)),
+        CpuInstruction::Add(InstructionArgument::Stack(0), InstructionArgument::Value(4)),
+        CpuInstruction::Nop(),
+    ]);
+    cpu.start().unwrap();
+    while cpu.get_register(Register::Ip) != 3 {
+        assert!(cpu.step());
+    }
+
+    let before = cpu.snapshot();
+    let cycles = cpu.get_cycles();
+    assert!(cpu.step());
+    let after = cpu.snapshot();
+    assert_eq!(before.registers(), after.registers());
+    assert_eq!(before.memory(), after.memory());
+    assert_eq!(after.registers().res, 7);
+    assert_eq!(cpu.get_cycles(), cycles + 1);
+}
+
+#[test]
+fn an_unknown_register_name_lists_the_valid_ones() {
+    let error = "z".parse::<Register>().unwrap_err();
+
+    assert!(matches!(&error, CpuError::UnknownRegister(name) if name == "z"));
+    let message = error.to_string();
+    assert!(message.starts_with("Register \"z\" not found"));
+    assert!(message.contains("r0, r1, r2"));
+    assert!(message.contains("r15, res, flags, cycles, ip, sp"));
+    assert!(message.contains("a, b, c, d as aliases"));
+    assert!(message.contains("f0, f1, f2, f3, fres"));
+}
+
+#[test]
+fn a_typo_in_a_register_name_fails_while_building_the_program() {
+    let source = "a z";
+    let arguments: Result<Vec<InstructionArgument>, _> = source
+        .split_whitespace()
+        .map(|name| name.parse().map(InstructionArgument::Register))
+        .collect();
+
+    let error = arguments.unwrap_err();
+    assert!(matches!(&error, CpuError::UnknownRegister(name) if name == "z"));
+    assert!(matches!(
+        "fz".parse::<FloatRegister>(),
+        Err(CpuError::UnknownRegister(name)) if name == "fz"
+    ));
+    assert_eq!("b".parse::<Register>().unwrap(), Register::B);
+}
+
+#[test]
+fn every_writable_register_round_trips_through_mov() {
+    let read_only = [Register::Cycles, Register::Ip, Register::Sp];
+    let writable = Register::ALL
+        .into_iter()
+        .filter(|register| !read_only.contains(register));
+    for (value, register) in (100..).zip(writable) {
+        let (cpu, output) = run_main(vec![
+            CpuInstruction::Mov(InstructionArgument::Value(value), reg(register)),
+            CpuInstruction::Mov(reg(register), InstructionArgument::Stack(0)),
+            CpuInstruction::Print(InstructionArgument::Stack(0)),
+        ]);
+
+        assert_eq!(output, format!("{value}\n"), "through {register}");
+        assert_eq!(cpu.get_register(register), value, "through {register}");
+    }
+}
+
+#[test]
+fn every_register_name_round_trips_through_display_and_from_str() {
+    for register in Register::ALL {
+        assert_eq!(register.to_string().parse::<Register>().unwrap(), register);
+    }
+    for register in FloatRegister::ALL {
+        assert_eq!(
+            register.to_string().parse::<FloatRegister>().unwrap(),
+            register
+        );
+    }
+}
+
+#[test]
+fn the_sixteen_general_registers_are_shared_across_calls() {
+    let general = &Register::ALL[..16];
+    let mut program: Vec<CpuInstruction> = vec![CpuInstruction::Fn("main".into(), 0)];
+    program.extend((1..).zip(general).map(|(value, &register)| {
+        CpuInstruction::Mov(InstructionArgument::Value(value * 11), reg(register))
+    }));
+    program.extend([
+        CpuInstruction::Call("callee".into()),
+        CpuInstruction::Ret(),
+        // The callee sees the values of its caller, and its own writes are seen after it returns
+        CpuInstruction::Fn("callee".into(), 0),
+        CpuInstruction::Assert(reg(Register::R7), InstructionArgument::Value(88)),
+        CpuInstruction::Mov(InstructionArgument::Value(0), reg(Register::R15)),
+        CpuInstruction::Ret(),
+    ]);
+    let (mut cpu, _) = load(&program);
+    cpu.execute().unwrap();
+
+    let values: Vec<_> = general
+        .iter()
+        .map(|&register| cpu.get_register(register))
+        .collect();
+    let mut expected: Vec<_> = (1..=16).map(|value| value * 11).collect();
+    expected[15] = 0;
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn the_letter_registers_are_aliases_of_the_first_four() {
+    assert_eq!(Register::A, Register::R0);
+    assert_eq!(Register::D, Register::R3);
+    for (name, alias) in [("a", "r0"), ("b", "r1"), ("c", "r2"), ("d", "r3")] {
+        let (mut cpu, _) = load_main(vec![CpuInstruction::Print(reg(Register::Res))]);
+        cpu.set_register(name, 5).unwrap();
+
+        assert_eq!(
+            cpu.get_register(alias.parse().unwrap()),
+            5,
+            "{name} is {alias}"
+        );
+        assert_eq!(name.parse::<Register>().unwrap().to_string(), alias);
+    }
+}
+
+#[test]
+fn ip_reads_the_address_of_the_executing_instruction() {
+    let (_, output) = run_main(vec![
+        CpuInstruction::Mov(reg(Register::Ip), InstructionArgument::Stack(0)),
+        CpuInstruction::Nop(),
+        CpuInstruction::Nop(),
+        CpuInstruction::Mov(reg(Register::Ip), InstructionArgument::Stack(1)),
+        CpuInstruction::Print(InstructionArgument::Stack(0)),
+        CpuInstruction::Sub(InstructionArgument::Stack(1), InstructionArgument::Stack(0)),
+        CpuInstruction::Print(reg(Register::Res)),
+    ]);
+
+    assert_eq!(output, "1\n3\n");
+}
+
+#[test]
+fn ip_cannot_be_written() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Mov(
+        InstructionArgument::Value(0),
+        reg(Register::Ip),
+    )]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::ReadOnlyRegister(Register::Ip)
+    ));
+    assert!(
+        error
+            .to_string()
+            .contains("Cannot write to the read-only register \"ip\"")
+    );
+}
+
+#[test]
+fn sp_counts_the_sub_stacks_of_the_calls_in_progress() {
+    let (mut cpu, output) = load::<u16>(&[
+        CpuInstruction::Fn("main".into(), 0),
+        CpuInstruction::Print(reg(Register::Sp)),
+        CpuInstruction::Call("recurse".into()),
+        CpuInstruction::Print(reg(Register::Sp)),
+        CpuInstruction::Ret(),
+        CpuInstruction::Fn("recurse".into(), 0),
+        CpuInstruction::Print(reg(Register::Sp)),
+        CpuInstruction::Lt(reg(Register::Sp), InstructionArgument::Value(4)),
+        CpuInstruction::CallIf(reg(Register::Res), "recurse".into()),
+        CpuInstruction::Ret(),
+    ]);
+    cpu.execute().unwrap();
+
+    assert_eq!(output.text(), "1\n2\n3\n4\n1\n");
+}
+
+#[test]
+fn sp_cannot_be_written() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Inc(reg(Register::Sp))]);
+
+    let error = cpu.execute().unwrap_err();
+    assert!(matches!(
+        error.inner(),
+        CpuError::ReadOnlyRegister(Register::Sp)
+    ));
+}
+
+#[test]
+fn registers_set_by_the_host_reach_the_program() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Mul(
+        reg(Register::A),
+        reg(Register::B),
+    )]);
+    cpu.set_registers(&[("a", 6), ("b", 7)]).unwrap();
+
+    let report = cpu.execute().unwrap();
+    assert_eq!(report.registers.res, 42);
+    assert_eq!(cpu.get_register(Register::Res), 42);
+}
+
+#[test]
+fn setting_an_unknown_register_changes_nothing() {
+    let (mut cpu, _) = load_main(vec![]);
+
+    let error = cpu.set_register("nope", 1).unwrap_err();
+    assert!(matches!(error, CpuError::UnknownRegister(name) if name == "nope"));
+    // The valid names before the unknown one aren't set either
+    let error = cpu.set_registers(&[("a", 6), ("nope", 7)]).unwrap_err();
+    assert!(matches!(error, CpuError::UnknownRegister(name) if name == "nope"));
+    assert_eq!(cpu.get_register(Register::A), 0);
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::{load_main, reg, run_main};
+use emulated_cpu::cpu::{CpuInstruction, InstructionArgument, Register};
+
+/** The number of cycles "main" lasts when running the instruction once */
+fn cycles_of(instruction: CpuInstruction) -> u64 {
+    let (mut cpu, _) = load_main(vec![instruction]);
+    cpu.start().unwrap();
+    while cpu.get_register(Register::Ip) != 1 {
+        assert!(cpu.step());
+    }
+
+    let before = cpu.get_cycles();
+    assert!(cpu.step());
+    cpu.get_cycles() - before
+}
+
+#[test]
+fn wait_advances_the_cycle_counter_by_its_argument() {
+    assert_eq!(
+        cycles_of(CpuInstruction::Wait(InstructionArgument::Value(10))),
+        10
+    );
+    assert_eq!(
+        cycles_of(CpuInstruction::Wait(InstructionArgument::Value(1))),
+        1
+    );
+}
+
+#[test]
+fn wait_reads_its_duration_from_a_register() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(7), reg(Register::A)),
+        CpuInstruction::Mov(reg(Register::Cycles), reg(Register::B)),
+        CpuInstruction::Wait(reg(Register::A)),
+        CpuInstruction::Mov(reg(Register::Cycles), reg(Register::C)),
+    ]);
+
+    // The WAIT and the MOV reading the counter again
+    assert_eq!(
+        cpu.get_register(Register::C) - cpu.get_register(Register::B),
+        8
+    );
+}
+
+#[test]
+fn wait_zero_lasts_a_single_cycle_like_nop() {
+    assert_eq!(
+        cycles_of(CpuInstruction::Wait(InstructionArgument::Value(0))),
+        1
+    );
+    assert_eq!(cycles_of(CpuInstruction::Nop()), 1);
+}
+
+#[test]
+fn wait_does_not_sleep_when_running_as_fast_as_possible() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Wait(InstructionArgument::Value(
+        60000,
+    ))]);
+    let start = std::time::Instant::now();
+    cpu.execute().unwrap();
+
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert!(cpu.get_cycles() >= 60000);
+}
+
+#[test]
+fn every_instruction_lasts_one_cycle() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Mov(InstructionArgument::Value(1), reg(Register::A)),
+        CpuInstruction::Add(reg(Register::A), InstructionArgument::Value(2)),
+        CpuInstruction::Print(reg(Register::Res)),
+    ]);
+
+    // The call to "main" and its RET last a cycle each too
+    assert_eq!(cpu.get_cycles(), 3 + 2);
+}
+
+#[test]
+fn a_loop_lasts_one_cycle_per_executed_instruction() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Inc(reg(Register::A)),
+        CpuInstruction::Lt(reg(Register::A), InstructionArgument::Value(10)),
+        CpuInstruction::Jnz(reg(Register::Res), "loop".into()),
+    ]);
+
+    // The label is executed on every iteration as well
+    assert_eq!(cpu.get_cycles(), 10 * 4 + 2);
+    assert_eq!(cpu.get_register(Register::A), 10);
+}
+
+#[test]
+fn an_if_and_its_branch_last_a_single_cycle() {
+    let (cpu, _) = run_main(vec![CpuInstruction::If(
+        InstructionArgument::Value(1),
+        Box::new(CpuInstruction::Inc(reg(Register::A))),
+        Box::new(CpuInstruction::Nop()),
+    )]);
+
+    assert_eq!(cpu.get_cycles(), 1 + 2);
+    assert_eq!(cpu.get_register(Register::A), 1);
+}
+
+#[test]
+fn programs_read_the_cycle_counter_through_a_register() {
+    let (cpu, _) = run_main(vec![
+        CpuInstruction::Nop(),
+        CpuInstruction::Nop(),
+        CpuInstruction::Mov(reg(Register::Cycles), reg(Register::A)),
+    ]);
+
+    // The call to "main" and both NOPs were executed before the MOV
+    assert_eq!(cpu.get_register(Register::A), 3);
+}
+
+#[test]
+fn the_report_counts_the_same_cycles() {
+    let (mut cpu, _) = load_main(vec![CpuInstruction::Wait(InstructionArgument::Value(5))]);
+    let report = cpu.execute().unwrap();
+
+    assert_eq!(report.cycles, 5 + 2);
+    assert_eq!(report.instructions, 3);
+    assert_eq!(report.cycles, cpu.get_cycles());
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::load;
+use emulated_cpu::cpu::{
+    Cpu16, CpuInstruction, CpuState, ExitReason, InstructionArgument, Register, Word,
+};
+
+/** Increments "a" until it reaches the limit, then prints it */
+fn counting_program<W: Word>(limit: W) -> Vec<CpuInstruction<W>> {
+    let a = InstructionArgument::Register(Register::A);
+    vec![
+        CpuInstruction::Fn("main".into(), W::ZERO),
+        CpuInstruction::Label("loop".into()),
+        CpuInstruction::Inc(a),
+        CpuInstruction::Eq(a, InstructionArgument::Value(limit)),
+        CpuInstruction::Jz(InstructionArgument::Register(Register::Res), "loop".into()),
+        CpuInstruction::Print(a),
+        CpuInstruction::Ret(),
+    ]
+}
+
+#[test]
+fn counting_program_runs_to_completion() {
+    let (mut cpu, output) = load(&counting_program(100u16));
+
+    let report = cpu.execute().unwrap();
+    assert!(matches!(report.exit, ExitReason::Finished));
+    assert_eq!(report.registers.general[Register::A as usize], 100);
+    assert_eq!(cpu.get_register_by_name("a").unwrap(), 100);
+    assert_eq!(output.text(), "100\n");
+}
+
+#[test]
+fn counting_program_runs_on_every_word_size() {
+    let (mut cpu, output) = load(&counting_program(200u8));
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "200\n");
+
+    let (mut cpu, output) = load(&counting_program(200u32));
+    cpu.execute().unwrap();
+    assert_eq!(output.text(), "200\n");
+}
+
+#[test]
+fn arithmetic_wraps_around_at_the_word_size() {
+    fn increment<W: Word>(value: W) -> W {
+        let a = InstructionArgument::Register(Register::A);
+        let (mut cpu, _) = load(&[
+            CpuInstruction::Fn("main".into(), W::ZERO),
+            CpuInstruction::Inc(a),
+            CpuInstruction::Ret(),
+        ]);
+        cpu.set_register("a", value).unwrap();
+
+        cpu.execute().unwrap();
+        cpu.get_register(Register::A)
+    }
+
+    assert_eq!(increment(u8::MAX), 0);
+    assert_eq!(increment(255u16), 256);
+    assert_eq!(increment(u16::MAX), 0);
+    assert_eq!(increment(u16::MAX as u32), 65536);
+    assert_eq!(increment(u32::MAX), 0);
+}
+
+#[test]
+fn the_default_word_is_16_bit() {
+    let mut cpu = Cpu16::new(0);
+    cpu.append_instructions(&[CpuInstruction::Fn("main".into(), 0), CpuInstruction::Ret()])
+        .unwrap();
+
+    let report = cpu.execute().unwrap();
+    let res: u16 = report.registers.res;
+    assert_eq!(res, 0);
+}
+
+#[test]
+fn the_heap_is_usable_on_every_word_size() {
+    /** Allocates two blocks with the default dense backing, and returns their addresses */
+    fn allocate_twice<W: Word>() -> (W, W) {
+        let (mut cpu, _): (CpuState<W>, _) = load(&[
+            CpuInstruction::Fn("main".into(), W::ZERO),
+            CpuInstruction::Alloc(InstructionArgument::Value(W::from_u64(3))),
+            CpuInstruction::Mov(
+                InstructionArgument::Register(Register::Res),
+                InstructionArgument::Register(Register::A),
+            ),
+            CpuInstruction::Alloc(InstructionArgument::Value(W::from_u64(2))),
+            CpuInstruction::Ret(),
+        ]);
+
+        let report = cpu.execute().unwrap();
+        (
+            report.registers.general[Register::A as usize],
+            report.registers.res,
+        )
+    }
+
+    assert_eq!(allocate_twice::<u8>(), (0x80, 0x83));
+    assert_eq!(allocate_twice::<u16>(), (0x8000, 0x8003));
+    assert_eq!(allocate_twice::<u32>(), (0x8000, 0x8003));
+}